@@ -0,0 +1,154 @@
+use bevy::{input::mouse::MouseMotion, prelude::*};
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::*};
+use shared::prelude::*;
+
+/// Mouse aiming: points the ship towards the cursor, by writing the `Aim` axis of our
+/// `ActionState`. Gamepad sticks are bound to `Aim` directly in the `InputMap`.
+///
+/// The ActionState is networked by the LeafwingInputPlugin like any other input, so the server
+/// and other clients see the same aim direction.
+pub(crate) fn plugin(app: &mut App) {
+    app.init_resource::<MouseAim>();
+    app.init_resource::<KeyBindings>();
+    app.add_systems(
+        Update,
+        apply_key_bindings.run_if(resource_changed::<KeyBindings>),
+    );
+    app.add_systems(
+        PreUpdate,
+        (choose_aim_source, apply_mouse_aim)
+            .chain()
+            .in_set(InputManagerSystem::ManualControl),
+    );
+}
+
+/// Whether the mouse is steering. Moving the mouse turns it on, turning with the keyboard
+/// turns it off, so the cursor sitting still doesn't fight the arrow keys.
+#[derive(Resource, Default)]
+struct MouseAim(bool);
+
+/// Keyboard bindings, which can be changed in the settings menu. An action can have several keys,
+/// the first one is the one shown and changed in the menu.
+#[derive(Resource, Debug, Clone)]
+pub(crate) struct KeyBindings(pub Vec<(PlayerActions, KeyCode)>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(vec![
+            (PlayerActions::Up, KeyCode::ArrowUp),
+            (PlayerActions::Down, KeyCode::ArrowDown),
+            (PlayerActions::Left, KeyCode::ArrowLeft),
+            (PlayerActions::Right, KeyCode::ArrowRight),
+            (PlayerActions::Up, KeyCode::KeyW),
+            (PlayerActions::Down, KeyCode::KeyS),
+            (PlayerActions::Left, KeyCode::KeyA),
+            (PlayerActions::Right, KeyCode::KeyD),
+            (PlayerActions::Fire, KeyCode::Space),
+        ])
+    }
+}
+
+impl KeyBindings {
+    /// The actions that can be rebound
+    pub const ACTIONS: [PlayerActions; 5] = [
+        PlayerActions::Up,
+        PlayerActions::Down,
+        PlayerActions::Left,
+        PlayerActions::Right,
+        PlayerActions::Fire,
+    ];
+
+    pub fn primary(&self, action: PlayerActions) -> Option<KeyCode> {
+        self.0
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map(|(_, key)| *key)
+    }
+
+    /// Makes `key` the action's primary key. The key is taken away from any other action.
+    pub fn rebind(&mut self, action: PlayerActions, key: KeyCode) {
+        self.0
+            .retain(|(bound, bound_key)| *bound == action || *bound_key != key);
+        match self.0.iter_mut().find(|(bound, _)| *bound == action) {
+            Some((_, primary)) => *primary = key,
+            None => self.0.insert(0, (action, key)),
+        }
+    }
+}
+
+/// The keyboard, mouse and gamepad bindings for our own ship
+pub(crate) fn player_input_map(bindings: &KeyBindings) -> InputMap<PlayerActions> {
+    InputMap::new(bindings.0.iter().copied())
+        .with(PlayerActions::Fire, MouseButton::Left)
+        .with(PlayerActions::Fire, GamepadButtonType::RightTrigger2)
+        .with_dual_axis(PlayerActions::Aim, GamepadStick::RIGHT)
+        .with_axis(PlayerActions::Thrust, GamepadControlAxis::LEFT_Y)
+}
+
+/// Rebuilds our ship's input map when the bindings change
+fn apply_key_bindings(
+    bindings: Res<KeyBindings>,
+    q: Query<
+        Entity,
+        (
+            With<Controlled>,
+            With<Predicted>,
+            With<InputMap<PlayerActions>>,
+        ),
+    >,
+    mut commands: Commands,
+) {
+    for entity in q.iter() {
+        commands.entity(entity).insert(player_input_map(&bindings));
+    }
+}
+
+fn choose_aim_source(
+    mut mouse_aim: ResMut<MouseAim>,
+    mut motion: EventReader<MouseMotion>,
+    q: Query<&ActionState<PlayerActions>, (With<Controlled>, With<Predicted>)>,
+) {
+    let Ok(action) = q.get_single() else {
+        return;
+    };
+    let moved = motion.read().count() > 0;
+    if action.pressed(&PlayerActions::Left) || action.pressed(&PlayerActions::Right) {
+        mouse_aim.0 = false;
+    } else if moved {
+        mouse_aim.0 = true;
+    }
+}
+
+pub(crate) fn apply_mouse_aim(
+    mouse_aim: Res<MouseAim>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut q: Query<(&Position, &mut ActionState<PlayerActions>), (With<Controlled>, With<Predicted>)>,
+) {
+    if !mouse_aim.0 {
+        return;
+    }
+    let Ok((position, mut action)) = q.get_single_mut() else {
+        return;
+    };
+    // the menu is open
+    if action.disabled() {
+        return;
+    }
+    // a gamepad stick takes priority over the mouse
+    if action.axis_pair(&PlayerActions::Aim).length() > AIM_DEADZONE {
+        return;
+    }
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    let Some(cursor_world) = cameras
+        .get_single()
+        .ok()
+        .and_then(|(camera, transform)| camera.viewport_to_world_2d(transform, cursor))
+    else {
+        return;
+    };
+    let aim = (cursor_world - position.0).normalize_or_zero();
+    action.set_axis_pair(&PlayerActions::Aim, aim);
+}
@@ -1,4 +1,11 @@
+use crate::aim;
+use crate::identity;
+use crate::menu;
+use crate::notices;
+use crate::reconnect;
+use crate::scoreboard;
 use crate::screens;
+use crate::touch;
 use bevy::prelude::*;
 #[cfg(feature = "bevygap")]
 use bevygap_client_plugin::prelude::*;
@@ -10,7 +17,10 @@ use shared::prelude::*;
 
 /// The game name sent to the matchmaker when requesting a server to play on
 pub const GAME_NAME: &str = "bevygap-spaceships";
-/// The game version sent to the matchmaker when requesting a server to play on
+/// The game version sent to the matchmaker when requesting a server to play on. This picks the
+/// Edgegap app version, so the matchmaker's `--app-version` must match. Deployments are
+/// registered against it, so only change it along with a new Edgegap app version, and say so in
+/// the release notes. Protocol compatibility is checked by the `ProtocolFingerprint` instead.
 pub const GAME_VERSION: &str = "1";
 
 pub struct BevygapSpaceshipsClientPlugin;
@@ -19,6 +29,13 @@ impl Plugin for BevygapSpaceshipsClientPlugin {
     fn build(&self, app: &mut App) {
         // will default to the Connect screen with a button to initiate
         app.add_plugins(screens::plugin);
+        app.add_plugins(notices::plugin);
+        app.add_plugins(aim::plugin);
+        app.add_plugins(identity::plugin);
+        app.add_plugins(touch::plugin);
+        app.add_plugins(scoreboard::plugin);
+        app.add_plugins(reconnect::plugin);
+        app.add_plugins(menu::plugin);
 
         #[cfg(feature = "bevygap")]
         {
@@ -62,7 +79,14 @@ impl Plugin for BevygapSpaceshipsClientPlugin {
         );
         app.add_systems(
             Update,
-            (add_ball_physics, add_bullet_physics, handle_new_player),
+            (
+                add_ball_physics,
+                add_asteroid_physics,
+                add_bullet_physics,
+                handle_new_player,
+                handle_ship_class_change,
+                handle_goal_scored,
+            ),
         );
         app.add_systems(
             FixedUpdate,
@@ -73,9 +97,18 @@ impl Plugin for BevygapSpaceshipsClientPlugin {
 
         app.add_systems(
             Update,
-            render_server_metadata
-                .run_if(resource_exists::<ServerMetadata>)
-                .run_if(resource_changed::<ServerMetadata>),
+            (
+                sync_arena_with_server.run_if(resource_changed::<CurrentMap>),
+                render_server_metadata.run_if(
+                    resource_changed::<ServerMetadata>.or_else(resource_changed::<CurrentMap>),
+                ),
+            )
+                .chain(),
+        );
+        app.add_systems(
+            Update,
+            render_team_scores
+                .run_if(resource_changed::<TeamScores>.or_else(resource_changed::<MatchRules>)),
         );
 
         #[cfg(target_family = "wasm")]
@@ -156,7 +189,11 @@ pub(crate) fn connect_client_observer(
 ) {
     info!("Connecting...");
     match state.get() {
-        BevygapClientState::Dormant | BevygapClientState::Error(_, _) => {
+        // Finished means we connected once, and are reconnecting after losing the connection.
+        // Connect tokens are single use, so we ask the matchmaker again.
+        BevygapClientState::Dormant
+        | BevygapClientState::Error(_, _)
+        | BevygapClientState::Finished => {
             commands.bevygap_connect_client();
         }
         _ => {
@@ -169,8 +206,22 @@ pub(crate) fn connect_client_observer(
 #[cfg(not(feature = "bevygap"))]
 pub(crate) fn connect_client_observer(
     _trigger: Trigger<crate::screens::ConnectToServerRequest>,
+    #[cfg(not(target_family = "wasm"))] selected: Res<screens::SelectedServer>,
+    #[cfg(not(target_family = "wasm"))] mut config: ResMut<ClientConfig>,
     mut commands: Commands,
 ) {
+    // point the connection at the server picked in the server browser
+    #[cfg(not(target_family = "wasm"))]
+    {
+        if let NetConfig::Netcode { auth, io, .. } = &mut config.net {
+            if let Authentication::Manual { server_addr, .. } = auth {
+                *server_addr = selected.0;
+            }
+            if let ClientTransport::WebTransportClient { server_addr, .. } = &mut io.transport {
+                *server_addr = selected.0;
+            }
+        }
+    }
     info!("Connecting...");
     commands.connect_client();
 }
@@ -180,16 +231,55 @@ fn on_bevygap_state_change(state: Res<State<BevygapClientState>>) {
     info!("Bevygap client state = {state:?}");
 }
 
-fn render_server_metadata(mut commands: Commands, metadata: Res<ServerMetadata>) {
-    if metadata.fqdn.is_empty() {
+/// The server tells us which arena it's using, and changes it when rotating maps.
+/// Load it if it isn't the one we have; the shared plugin then rebuilds the walls.
+fn sync_arena_with_server(current_map: Res<CurrentMap>, arena: Res<Arena>, mut commands: Commands) {
+    if current_map.name.is_empty() || current_map.name == arena.def.name {
+        return;
+    }
+    info!("Server is using arena '{}', loading it", current_map.name);
+    match Arena::load(&current_map.name) {
+        Ok(new_arena) => commands.insert_resource(new_arena),
+        Err(e) => error!("Can't load server's arena: {e}"),
+    }
+}
+
+/// Marker for the text showing which server we're connected to
+#[derive(Component)]
+struct ServerMetadataText;
+
+fn render_server_metadata(
+    mut commands: Commands,
+    metadata: Res<ServerMetadata>,
+    current_map: Res<CurrentMap>,
+    mut q: Query<&mut Text, With<ServerMetadataText>>,
+) {
+    if metadata.fqdn.is_empty() && metadata.max_players == 0 {
         return;
     }
     // logs will include the build info: timestamp and git sha of server you've connected to.
     // but this isn't shown in the UI.
     info!("Got server metadata: {:?}", metadata);
-    commands.spawn(
+    let mut msg = if metadata.fqdn.is_empty() {
+        format!("Players {}/{}", metadata.players, metadata.max_players)
+    } else {
+        format!(
+            "Server {} @ {} - Players {}/{}",
+            metadata.fqdn, metadata.location, metadata.players, metadata.max_players
+        )
+    };
+    if !current_map.name.is_empty() {
+        msg.push_str(&format!(" - Map: {}", current_map.name));
+    }
+    if let Ok(mut text) = q.get_single_mut() {
+        text.sections[0].value = msg;
+        return;
+    }
+    commands.spawn((
+        ServerMetadataText,
+        StateScoped(screens::Screen::Gameplay),
         TextBundle::from_section(
-            format!("Server {} @ {}", metadata.fqdn, metadata.location),
+            msg,
             TextStyle {
                 font_size: 16.0,
                 color: bevy::color::palettes::css::WHITE.into(),
@@ -202,18 +292,97 @@ fn render_server_metadata(mut commands: Commands, metadata: Res<ServerMetadata>)
             left: Val::Px(5.0),
             ..default()
         }),
-    );
+    ));
 }
 
-/// Listen for events to know when the client is connected, and spawn a text entity
-/// to display the client id
+/// Marker for the team scores text
+#[derive(Component)]
+struct TeamScoresText;
+
+fn render_team_scores(
+    mut commands: Commands,
+    rules: Res<MatchRules>,
+    scores: Res<TeamScores>,
+    mut q: Query<&mut Text, With<TeamScoresText>>,
+) {
+    let sections = if rules.mode.has_teams() {
+        let style = |color: Color| TextStyle {
+            font_size: 24.0,
+            color,
+            ..default()
+        };
+        vec![
+            TextSection::new(format!("{}\n", rules.mode.name()), style(Color::WHITE)),
+            TextSection::new(
+                format!("{} {}", Team::Red.name(), scores.get(Team::Red)),
+                style(Team::Red.color()),
+            ),
+            TextSection::new(" - ", style(Color::WHITE)),
+            TextSection::new(
+                format!("{} {}", scores.get(Team::Blue), Team::Blue.name()),
+                style(Team::Blue.color()),
+            ),
+        ]
+    } else {
+        vec![]
+    };
+    if let Ok(mut text) = q.get_single_mut() {
+        text.sections = sections;
+        return;
+    }
+    commands.spawn((
+        TeamScoresText,
+        StateScoped(screens::Screen::Gameplay),
+        TextBundle::from_sections(sections)
+            .with_text_justify(JustifyText::Center)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                right: Val::Px(5.0),
+                ..default()
+            }),
+    ));
+}
+
+/// Marker for the text showing our client id
+#[derive(Component)]
+struct ClientIdText;
+
+/// Listen for events to know when the client is connected, say hello to the server, and spawn
+/// a text entity to display the client id
 pub(crate) fn handle_connection(
     mut commands: Commands,
     mut connection_event: EventReader<client::ConnectEvent>,
+    client_id_text: Query<Entity, With<ClientIdText>>,
+    mut connection_manager: ResMut<client::ConnectionManager>,
+    selected_ship_class: Res<screens::SelectedShipClass>,
+    identity: Res<identity::IdentityToken>,
+    fingerprint: Res<ProtocolFingerprint>,
 ) {
     for event in connection_event.read() {
         let client_id = event.client_id();
-        commands.spawn(
+        // sent first, so the server can turn us away if we're out of date
+        let mut check = ProtocolCheck {
+            fingerprint: fingerprint.0,
+        };
+        if let Err(e) = connection_manager.send_message::<ProtocolCheckChannel, _>(&mut check) {
+            error!("Failed to send protocol check: {e:?}");
+        }
+        let mut hello = ClientHello {
+            ship_class: selected_ship_class.0,
+            identity: identity.0.clone(),
+        };
+        info!("Connected, sending hello as a {}", hello.ship_class.name());
+        if let Err(e) = connection_manager.send_message::<ClientChannel, _>(&mut hello) {
+            error!("Failed to send hello: {e:?}");
+        }
+        // we get a new client id each time we reconnect
+        for entity in client_id_text.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        commands.spawn((
+            ClientIdText,
+            StateScoped(screens::Screen::Gameplay),
             TextBundle::from_section(
                 format!("Client {}", client_id),
                 TextStyle {
@@ -228,7 +397,7 @@ pub(crate) fn handle_connection(
                 left: Val::Px(5.0),
                 ..default()
             }),
-        );
+        ));
     }
 }
 
@@ -248,6 +417,16 @@ fn add_ball_physics(
     }
 }
 
+fn add_asteroid_physics(
+    mut commands: Commands,
+    mut asteroid_query: Query<(Entity, &Asteroid), Added<Predicted>>,
+) {
+    for (entity, asteroid) in asteroid_query.iter_mut() {
+        info!("Adding physics to a replicated asteroid {entity:?}");
+        commands.entity(entity).insert(asteroid.physics_bundle());
+    }
+}
+
 /// Simliar blueprint scenario as balls, except sometimes clients prespawn bullets ahead of server
 /// replication, which means they will already have the physics components.
 /// So, we filter the query using `Without<Collider>`.
@@ -266,30 +445,39 @@ fn add_bullet_physics(
 #[allow(clippy::type_complexity)]
 fn handle_new_player(
     connection: Res<ClientConnection>,
+    bindings: Res<aim::KeyBindings>,
     mut commands: Commands,
-    mut player_query: Query<(Entity, Has<Controlled>), (Added<Predicted>, With<Player>)>,
+    mut player_query: Query<
+        (Entity, Has<Controlled>, &ShipClass),
+        (Added<Predicted>, With<Player>),
+    >,
 ) {
-    for (entity, is_controlled) in player_query.iter_mut() {
+    for (entity, is_controlled, ship_class) in player_query.iter_mut() {
         // is this our own entity?
         if is_controlled {
             info!("Own player replicated to us, adding inputmap {entity:?}");
-            commands.entity(entity).insert(InputMap::new([
-                (PlayerActions::Up, KeyCode::ArrowUp),
-                (PlayerActions::Down, KeyCode::ArrowDown),
-                (PlayerActions::Left, KeyCode::ArrowLeft),
-                (PlayerActions::Right, KeyCode::ArrowRight),
-                (PlayerActions::Up, KeyCode::KeyW),
-                (PlayerActions::Down, KeyCode::KeyS),
-                (PlayerActions::Left, KeyCode::KeyA),
-                (PlayerActions::Right, KeyCode::KeyD),
-                (PlayerActions::Fire, KeyCode::Space),
-            ]));
+            commands
+                .entity(entity)
+                .insert(aim::player_input_map(&bindings));
         } else {
             info!("Remote player replicated to us: {entity:?}");
         }
         let client_id = connection.id();
         info!(?entity, ?client_id, "adding physics to predicted player");
-        commands.entity(entity).insert(PhysicsBundle::player_ship());
+        commands.entity(entity).insert(ship_class.physics_bundle());
+    }
+}
+
+/// The server changes a ship's class when the player's hello arrives, which changes its hull.
+fn handle_ship_class_change(
+    mut commands: Commands,
+    q: Query<(Entity, Ref<ShipClass>), (With<Predicted>, With<Collider>)>,
+) {
+    for (entity, ship_class) in q.iter() {
+        if ship_class.is_changed() && !ship_class.is_added() {
+            info!("{entity:?} is now a {}", ship_class.name());
+            commands.entity(entity).insert(ship_class.physics_bundle());
+        }
     }
 }
 
@@ -310,6 +498,31 @@ fn handle_hit_event(
     }
 }
 
+// Celebrate goals with a big explosion in the scoring team's colour
+fn handle_goal_scored(
+    time: Res<Time>,
+    mut events: EventReader<client::MessageEvent<GoalScored>>,
+    players: Query<&Player, With<Predicted>>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let goal = event.message();
+        let scorer = goal
+            .scorer
+            .and_then(|id| players.iter().find(|p| p.client_id == id))
+            .map_or("Someone", |p| p.nickname.as_str());
+        info!("Goal! {scorer} scored for the {} team", goal.team.name());
+        commands.spawn((
+            SpatialBundle {
+                transform: Transform::from_xyz(goal.position.x, goal.position.y, 0.0),
+                ..default()
+            },
+            Explosion::new(time.elapsed(), goal.team.color())
+                .with_size(20.0, Duration::from_millis(600)),
+        ));
+    }
+}
+
 // only apply movements to predicted entities
 fn player_movement(
     mut q: Query<
@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use shared::prelude::*;
+
+/// A random token identifying this player across sessions, sent in our [`ClientHello`]. If we
+/// reconnect soon after dropping out, the server gives us back our name, colour and score.
+///
+/// Native clients keep it in a file (`IDENTITY_FILE` env, to run several clients on one machine),
+/// on the web it lives in localStorage.
+pub(crate) fn plugin(app: &mut App) {
+    app.insert_resource(load_or_create_identity());
+}
+
+#[cfg(not(target_family = "wasm"))]
+const DEFAULT_IDENTITY_FILE: &str = ".spaceships_identity";
+#[cfg(target_family = "wasm")]
+const IDENTITY_STORAGE_KEY: &str = "spaceships_identity";
+
+#[derive(Resource, Clone)]
+pub(crate) struct IdentityToken(pub String);
+
+fn load_or_create_identity() -> IdentityToken {
+    if let Some(identity) = load_identity().filter(|s| is_valid_identity(s)) {
+        info!("Loaded identity token");
+        return IdentityToken(identity);
+    }
+    let identity = new_identity();
+    info!("Created a new identity token");
+    store_identity(&identity);
+    IdentityToken(identity)
+}
+
+/// 128 random bits, as hex
+fn new_identity() -> String {
+    let (a, b) = random_u64_pair();
+    format!("{a:016x}{b:016x}")
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn random_u64_pair() -> (u64, u64) {
+    use std::hash::{BuildHasher, Hasher};
+    // std's RandomState is seeded from the OS, so hashing the time with it is random enough
+    let random = || {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hasher.finish()
+    };
+    (random(), random())
+}
+
+#[cfg(target_family = "wasm")]
+fn random_u64_pair() -> (u64, u64) {
+    let random = || {
+        let hi = (web_sys::js_sys::Math::random() * u32::MAX as f64) as u64;
+        let lo = (web_sys::js_sys::Math::random() * u32::MAX as f64) as u64;
+        (hi << 32) | lo
+    };
+    (random(), random())
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn identity_file() -> String {
+    std::env::var("IDENTITY_FILE").unwrap_or(DEFAULT_IDENTITY_FILE.to_string())
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn load_identity() -> Option<String> {
+    std::fs::read_to_string(identity_file())
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn store_identity(identity: &str) {
+    let path = identity_file();
+    if let Err(e) = std::fs::write(&path, identity) {
+        warn!("Failed to save identity token to {path}: {e}");
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+#[cfg(target_family = "wasm")]
+fn load_identity() -> Option<String> {
+    local_storage()?
+        .get_item(IDENTITY_STORAGE_KEY)
+        .ok()
+        .flatten()
+}
+
+#[cfg(target_family = "wasm")]
+fn store_identity(identity: &str) {
+    let Some(storage) = local_storage() else {
+        warn!("No localStorage, identity token won't survive a page reload");
+        return;
+    };
+    if let Err(e) = storage.set_item(IDENTITY_STORAGE_KEY, identity) {
+        warn!("Failed to save identity token: {e:?}");
+    }
+}
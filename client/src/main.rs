@@ -1,10 +1,19 @@
 use bevy::log::{Level, LogPlugin};
 use bevy::prelude::*;
 use client::{Authentication, ClientConfig, PredictionConfig};
+#[cfg(not(feature = "bevygap"))]
+use lightyear::connection::netcode::ConnectToken;
 
 use shared::prelude::*;
+mod aim;
 mod client_plugin;
+mod identity;
+mod menu;
+mod notices;
+mod reconnect;
+mod scoreboard;
 pub(crate) mod screens;
+mod touch;
 use client_plugin::*;
 
 fn main() {
@@ -17,6 +26,10 @@ fn main() {
             .set(AssetPlugin {
                 meta_check: bevy::asset::AssetMetaCheck::Never,
                 ..default()
+            })
+            .set(WindowPlugin {
+                primary_window: Some(primary_window()),
+                ..default()
             }),
     );
     app.add_plugins(LogPlugin {
@@ -50,6 +63,21 @@ fn main() {
     app.run();
 }
 
+/// On the web the canvas fills its parent element, so the game resizes with the browser and
+/// works on phones. The camera scales to fit whatever size the window ends up.
+fn primary_window() -> Window {
+    Window {
+        #[cfg(target_family = "wasm")]
+        fit_canvas_to_parent: true,
+        // stop the browser scrolling and zooming when playing with touch controls
+        #[cfg(target_family = "wasm")]
+        prevent_default_event_handling: true,
+        #[cfg(not(target_family = "wasm"))]
+        resolution: (800., 800.).into(),
+        ..default()
+    }
+}
+
 fn get_client_net_config() -> client::NetConfig {
     let client_addr = "0.0.0.0:0".parse().unwrap();
     // this gets overwritten if using connect tokens
@@ -73,6 +101,15 @@ fn get_client_net_config() -> client::NetConfig {
         private_key: DUMMY_PRIVATE_KEY,
         protocol_id: PROTOCOL_ID,
     };
+    // bevygap gets a token from the matchmaker, without it we can use one we minted ourselves
+    #[cfg(not(feature = "bevygap"))]
+    let auth = match read_connect_token() {
+        Some(token) => {
+            info!("Using connect token instead of the dummy key");
+            Authentication::Token(token)
+        }
+        None => auth,
+    };
 
     let netcode_config = client::NetcodeConfig::default();
 
@@ -99,3 +136,40 @@ fn get_client_net_config() -> client::NetConfig {
         io: io_config,
     }
 }
+
+/// Reads a base64 connect token from `LIGHTYEAR_CONNECT_TOKEN`, or from the file named by
+/// `LIGHTYEAR_CONNECT_TOKEN_FILE`. Mint one with `cargo run -p tools --bin connect_token`.
+///
+/// Tokens are single use, so reconnecting after losing the connection needs a fresh one.
+#[cfg(not(feature = "bevygap"))]
+fn read_connect_token() -> Option<ConnectToken> {
+    use base64::prelude::*;
+
+    let encoded = match std::env::var("LIGHTYEAR_CONNECT_TOKEN") {
+        Ok(token) => token,
+        Err(_) => {
+            let path = std::env::var("LIGHTYEAR_CONNECT_TOKEN_FILE").ok()?;
+            match std::fs::read_to_string(&path) {
+                Ok(token) => token,
+                Err(e) => {
+                    error!("Can't read connect token from {path}: {e:?}");
+                    return None;
+                }
+            }
+        }
+    };
+    let bytes = match BASE64_STANDARD.decode(encoded.trim()) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Connect token isn't valid base64: {e:?}");
+            return None;
+        }
+    };
+    match ConnectToken::try_from_bytes(&bytes) {
+        Ok(token) => Some(token),
+        Err(e) => {
+            error!("Invalid connect token: {e:?}");
+            None
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::aim::KeyBindings;
+use crate::screens::Screen;
+use bevy::prelude::*;
+use shared::prelude::*;
+
+/// The Escape menu: resume, settings, disconnect and quit. The game keeps running behind it,
+/// since the server doesn't stop for us. There's no audio setting, because the game has no audio.
+///
+/// Disconnecting just goes back to the connect screen. Leaving the gameplay screen is what
+/// closes the connection and clears out everything the server sent us.
+pub(crate) fn plugin(app: &mut App) {
+    app.init_resource::<Rebinding>();
+    app.add_systems(
+        Update,
+        (
+            toggle_menu,
+            capture_rebinding,
+            menu_button_system,
+            spawn_menu,
+            update_menu_labels,
+            block_game_input,
+        )
+            .chain()
+            .run_if(in_state(Screen::Gameplay)),
+    );
+    app.add_systems(OnExit(Screen::Gameplay), close_menu);
+}
+
+const MENU_KEY: KeyCode = KeyCode::Escape;
+
+const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
+
+/// Which page of the menu is open. Only exists while the menu is open.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuPage {
+    Main,
+    Settings,
+}
+
+/// The action waiting for a key press, after clicking its binding in the settings
+#[derive(Resource, Default, Debug)]
+struct Rebinding(Option<PlayerActions>);
+
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+enum MenuButton {
+    Resume,
+    Settings,
+    Back,
+    Disconnect,
+    Quit,
+    ToggleBloom,
+    ToggleDiagnostics,
+    Rebind(PlayerActions),
+}
+
+impl MenuButton {
+    fn label(
+        &self,
+        settings: &GraphicsSettings,
+        bindings: &KeyBindings,
+        rebinding: &Rebinding,
+    ) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        match self {
+            MenuButton::Resume => "Resume".to_string(),
+            MenuButton::Settings => "Settings".to_string(),
+            MenuButton::Back => "Back".to_string(),
+            MenuButton::Disconnect => "Disconnect".to_string(),
+            MenuButton::Quit => "Quit".to_string(),
+            MenuButton::ToggleBloom => format!("Bloom: {}", on_off(settings.bloom)),
+            MenuButton::ToggleDiagnostics => {
+                format!("Diagnostics: {}", on_off(settings.diagnostics))
+            }
+            MenuButton::Rebind(action) => {
+                let key = if rebinding.0 == Some(*action) {
+                    "press a key...".to_string()
+                } else {
+                    bindings
+                        .primary(*action)
+                        .map_or("unbound".to_string(), |key| format!("{key:?}"))
+                };
+                format!("{action:?}: {key}")
+            }
+        }
+    }
+}
+
+fn toggle_menu(
+    keys: Res<ButtonInput<KeyCode>>,
+    page: Option<Res<MenuPage>>,
+    rebinding: Res<Rebinding>,
+    mut commands: Commands,
+) {
+    // Escape while rebinding cancels the rebind, and is handled there
+    if !keys.just_pressed(MENU_KEY) || rebinding.0.is_some() {
+        return;
+    }
+    match page.as_deref() {
+        None => commands.insert_resource(MenuPage::Main),
+        Some(MenuPage::Settings) => commands.insert_resource(MenuPage::Main),
+        Some(MenuPage::Main) => close(&mut commands),
+    }
+}
+
+fn close(commands: &mut Commands) {
+    commands.remove_resource::<MenuPage>();
+    commands.insert_resource(Rebinding::default());
+}
+
+fn close_menu(mut commands: Commands) {
+    close(&mut commands);
+}
+
+fn capture_rebinding(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    let Some(key) = keys.get_just_pressed().next() else {
+        return;
+    };
+    if *key != MENU_KEY {
+        info!("Binding {action:?} to {key:?}");
+        bindings.rebind(action, *key);
+    }
+    rebinding.0 = None;
+}
+
+/// Respawns the menu when the page changes, or despawns it when closed
+fn spawn_menu(
+    page: Option<Res<MenuPage>>,
+    mut shown: Local<Option<MenuPage>>,
+    existing: Query<Entity, With<MenuRoot>>,
+    mut commands: Commands,
+) {
+    let page = page.map(|page| *page);
+    if page == *shown {
+        return;
+    }
+    *shown = page;
+    for entity in existing.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(page) = page else {
+        return;
+    };
+    let buttons = match page {
+        MenuPage::Main => {
+            let mut buttons = vec![
+                MenuButton::Resume,
+                MenuButton::Settings,
+                MenuButton::Disconnect,
+            ];
+            // closing the tab is how you quit on the web
+            if !cfg!(target_family = "wasm") {
+                buttons.push(MenuButton::Quit);
+            }
+            buttons
+        }
+        MenuPage::Settings => {
+            let mut buttons = vec![MenuButton::ToggleBloom, MenuButton::ToggleDiagnostics];
+            buttons.extend(KeyBindings::ACTIONS.map(MenuButton::Rebind));
+            buttons.push(MenuButton::Back);
+            buttons
+        }
+    };
+    commands
+        .spawn((
+            MenuRoot,
+            StateScoped(Screen::Gameplay),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.6).into(),
+                // draw over the rest of the HUD
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for button in buttons {
+                parent
+                    .spawn((
+                        button,
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(260.0),
+                                height: Val::Px(45.0),
+                                border: UiRect::all(Val::Px(3.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            border_color: BorderColor(Color::BLACK),
+                            border_radius: BorderRadius::all(Val::Px(8.0)),
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 20.0,
+                                color: Color::srgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn menu_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut settings: ResMut<GraphicsSettings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut exit: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    for (interaction, button, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {}
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+                continue;
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+                continue;
+            }
+        }
+        match button {
+            MenuButton::Resume => close(&mut commands),
+            MenuButton::Settings => commands.insert_resource(MenuPage::Settings),
+            MenuButton::Back => commands.insert_resource(MenuPage::Main),
+            MenuButton::Disconnect => {
+                info!("Disconnecting");
+                next_screen.set(Screen::Connect);
+            }
+            MenuButton::Quit => {
+                exit.send(AppExit::Success);
+            }
+            MenuButton::ToggleBloom => settings.bloom = !settings.bloom,
+            MenuButton::ToggleDiagnostics => settings.diagnostics = !settings.diagnostics,
+            MenuButton::Rebind(action) => rebinding.0 = Some(*action),
+        }
+    }
+}
+
+/// Stops the ship flying or firing while the menu is open, since clicking buttons and pressing
+/// keys to rebind would otherwise do both. Stays blocked after closing until the mouse button is
+/// released, so the click on Resume doesn't fire.
+fn block_game_input(
+    page: Option<Res<MenuPage>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut q: Query<&mut ActionState<PlayerActions>, (With<Controlled>, With<Predicted>)>,
+    mut awaiting_release: Local<bool>,
+) {
+    if page.is_some() {
+        *awaiting_release = true;
+    } else if !mouse.pressed(MouseButton::Left) {
+        *awaiting_release = false;
+    }
+    let block = *awaiting_release;
+    for mut action in q.iter_mut() {
+        if block && !action.disabled() {
+            action.disable();
+        } else if !block && action.disabled() {
+            action.enable();
+        }
+    }
+}
+
+fn update_menu_labels(
+    settings: Res<GraphicsSettings>,
+    bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    buttons: Query<(Ref<MenuButton>, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    let changed = settings.is_changed() || bindings.is_changed() || rebinding.is_changed();
+    for (button, children) in buttons.iter() {
+        if !changed && !button.is_added() {
+            continue;
+        }
+        let label = button.label(&settings, &bindings, &rebinding);
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value.clone_from(&label);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use bevy::{color::palettes::css, prelude::*};
+use shared::prelude::*;
+
+/// Renders [`ServerNotice`] messages from the server as a banner across the top of the screen.
+pub(crate) fn plugin(app: &mut App) {
+    app.init_resource::<ActiveNotice>();
+    app.add_systems(Startup, spawn_notice_banner);
+    app.add_systems(
+        Update,
+        (receive_server_notices, update_notice_banner).chain(),
+    );
+}
+
+/// The most recent notice, and the time we received it.
+#[derive(Resource, Default)]
+pub(crate) struct ActiveNotice(pub Option<(ServerNotice, Duration)>);
+
+#[derive(Component)]
+struct NoticeBanner;
+
+fn spawn_notice_banner(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Px(50.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                NoticeBanner,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: css::ORANGE.into(),
+                        ..default()
+                    },
+                ),
+            ));
+        });
+}
+
+fn receive_server_notices(
+    mut events: EventReader<client::MessageEvent<ServerNotice>>,
+    mut active: ResMut<ActiveNotice>,
+    time: Res<Time>,
+) {
+    for event in events.read() {
+        let notice = event.message().clone();
+        info!("Server notice: {notice:?}");
+        active.0 = Some((notice, time.elapsed()));
+    }
+}
+
+fn update_notice_banner(
+    active: Res<ActiveNotice>,
+    time: Res<Time>,
+    mut q: Query<&mut Text, With<NoticeBanner>>,
+) {
+    let Ok(mut text) = q.get_single_mut() else {
+        return;
+    };
+    let msg = match &active.0 {
+        None => String::new(),
+        Some((ServerNotice::ShuttingDown { seconds }, received_at)) => {
+            let elapsed = (time.elapsed() - *received_at).as_secs() as u32;
+            match seconds.saturating_sub(elapsed) {
+                0 => "Server shutting down".to_string(),
+                remaining => format!("Server shutting down in {remaining} seconds"),
+            }
+        }
+        Some((ServerNotice::IdleWarning { seconds }, received_at)) => {
+            let elapsed = (time.elapsed() - *received_at).as_secs() as u32;
+            format!(
+                "You are idle! Move or be disconnected in {} seconds",
+                seconds.saturating_sub(elapsed)
+            )
+        }
+        Some((ServerNotice::MapChanging { name, seconds }, received_at)) => {
+            let elapsed = (time.elapsed() - *received_at).as_secs() as u32;
+            match seconds.saturating_sub(elapsed) {
+                0 => String::new(),
+                remaining => format!("Next map: {name} in {remaining} seconds"),
+            }
+        }
+        Some((ServerNotice::IdleKicked, _)) => "Disconnected for being idle".to_string(),
+        // rejections are shown on the connect screen instead
+        Some((ServerNotice::Clear | ServerNotice::ConnectionRejected(_), _)) => String::new(),
+    };
+    if text.sections[0].value != msg {
+        text.sections[0].value = msg;
+    }
+}
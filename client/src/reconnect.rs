@@ -0,0 +1,206 @@
+use crate::screens::{ConnectToServerRequest, ConnectionLost, ConnectionRejected, Screen};
+use bevy::prelude::*;
+#[cfg(feature = "bevygap")]
+use bevygap_client_plugin::prelude::*;
+use lightyear::prelude::client::*;
+use shared::prelude::*;
+
+/// If the connection drops mid-game, show a "Reconnecting..." overlay and try to get back in,
+/// waiting longer between each attempt. With bevygap, each attempt goes through the matchmaker
+/// again, since connect tokens can't be reused. If every attempt fails, we go back to the
+/// connect screen and say why.
+pub(crate) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (start_reconnecting, reconnect, update_reconnecting_overlay)
+            .chain()
+            .run_if(in_state(Screen::Gameplay)),
+    );
+    app.add_systems(OnExit(Screen::Gameplay), stop_reconnecting);
+}
+
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+/// Delay before the first attempt, doubling each time
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(16);
+/// An attempt that hasn't connected by now has failed
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exists while we're trying to get back into the game
+#[derive(Resource, Debug)]
+struct Reconnecting {
+    /// Attempts made so far
+    attempts: u32,
+    phase: ReconnectPhase,
+}
+
+#[derive(Debug)]
+enum ReconnectPhase {
+    /// Backing off before the next attempt
+    Waiting(Timer),
+    /// An attempt is in progress, and times out when this finishes
+    Connecting(Timer),
+}
+
+impl Reconnecting {
+    fn retry_delay(attempts: u32) -> Duration {
+        (FIRST_RETRY_DELAY * 2u32.saturating_pow(attempts)).min(MAX_RETRY_DELAY)
+    }
+
+    fn wait(&mut self) {
+        self.phase = ReconnectPhase::Waiting(Timer::new(
+            Self::retry_delay(self.attempts),
+            TimerMode::Once,
+        ));
+    }
+}
+
+#[derive(Component)]
+struct ReconnectingOverlay;
+
+/// Everything the server replicated to us, and bullets we predicted
+pub(crate) type ReplicatedEntities<'w, 's> = Query<
+    'w,
+    's,
+    Entity,
+    Or<(
+        With<Confirmed>,
+        With<Predicted>,
+        With<Interpolated>,
+        With<BulletMarker>,
+    )>,
+>;
+
+/// Removes everything the server replicated to us. The server spawns it all again when we
+/// reconnect, and stale copies would linger forever.
+pub(crate) fn despawn_replicated_entities(q: &ReplicatedEntities, commands: &mut Commands) {
+    for entity in q.iter() {
+        if let Some(entity) = commands.get_entity(entity) {
+            entity.despawn_recursive();
+        }
+    }
+}
+
+fn start_reconnecting(
+    mut events: EventReader<DisconnectEvent>,
+    reconnecting: Option<Res<Reconnecting>>,
+    rejected: Option<Res<ConnectionRejected>>,
+    replicated: ReplicatedEntities,
+    mut commands: Commands,
+) {
+    if events.read().count() == 0 || reconnecting.is_some() {
+        return;
+    }
+    // the server told us why it's kicking us, so there's no point trying again
+    if rejected.is_some() {
+        return;
+    }
+    warn!("Lost connection to the server, reconnecting");
+    despawn_replicated_entities(&replicated, &mut commands);
+    let mut reconnecting = Reconnecting {
+        attempts: 0,
+        phase: ReconnectPhase::Waiting(Timer::default()),
+    };
+    reconnecting.wait();
+    commands.insert_resource(reconnecting);
+    commands.spawn((
+        ReconnectingOverlay,
+        StateScoped(Screen::Gameplay),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 40.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.7))
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            align_self: AlignSelf::Center,
+            justify_self: JustifySelf::Center,
+            padding: UiRect::all(Val::Px(20.0)),
+            ..default()
+        }),
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn reconnect(
+    reconnecting: Option<ResMut<Reconnecting>>,
+    mut connect_events: EventReader<ConnectEvent>,
+    mut disconnect_events: EventReader<DisconnectEvent>,
+    #[cfg(feature = "bevygap")] bevygap_state: Res<State<BevygapClientState>>,
+    overlay: Query<Entity, With<ReconnectingOverlay>>,
+    time: Res<Time>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut commands: Commands,
+) {
+    let Some(mut reconnecting) = reconnecting else {
+        return;
+    };
+    if connect_events.read().count() > 0 {
+        info!("Reconnected after {} attempts", reconnecting.attempts);
+        commands.remove_resource::<Reconnecting>();
+        for entity in overlay.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    let failed = disconnect_events.read().count() > 0;
+    #[cfg(feature = "bevygap")]
+    let failed = failed
+        || (bevygap_state.is_changed()
+            && matches!(bevygap_state.get(), BevygapClientState::Error(..)));
+
+    let attempts = reconnecting.attempts;
+    match &mut reconnecting.phase {
+        ReconnectPhase::Connecting(timeout) => {
+            if failed || timeout.tick(time.delta()).finished() {
+                warn!("Reconnect attempt {attempts} failed");
+                reconnecting.wait();
+            }
+        }
+        ReconnectPhase::Waiting(delay) => {
+            if !delay.tick(time.delta()).finished() {
+                return;
+            }
+            if attempts >= MAX_RECONNECT_ATTEMPTS {
+                warn!("Giving up reconnecting after {attempts} attempts");
+                commands.insert_resource(ConnectionLost(format!(
+                    "Lost connection to the server, and couldn't reconnect after {attempts} attempts"
+                )));
+                next_screen.set(Screen::Connect);
+                return;
+            }
+            reconnecting.attempts += 1;
+            info!("Reconnect attempt {}", reconnecting.attempts);
+            reconnecting.phase =
+                ReconnectPhase::Connecting(Timer::new(ATTEMPT_TIMEOUT, TimerMode::Once));
+            commands.trigger(ConnectToServerRequest);
+        }
+    }
+}
+
+fn update_reconnecting_overlay(
+    reconnecting: Option<Res<Reconnecting>>,
+    mut q: Query<&mut Text, With<ReconnectingOverlay>>,
+) {
+    let Some(reconnecting) = reconnecting else {
+        return;
+    };
+    if !reconnecting.is_changed() {
+        return;
+    }
+    let Ok(mut text) = q.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = match reconnecting.attempts {
+        0 => "Connection lost\nReconnecting...".to_string(),
+        n => format!("Connection lost\nReconnecting... (attempt {n} of {MAX_RECONNECT_ATTEMPTS})"),
+    };
+}
+
+fn stop_reconnecting(mut commands: Commands) {
+    commands.remove_resource::<Reconnecting>();
+}
@@ -0,0 +1,75 @@
+use crate::screens::Screen;
+use bevy::prelude::*;
+use shared::prelude::*;
+
+/// Hold Tab to show the scoreboard: everyone's score this session, and their lifetime stats
+/// from the server's stats store.
+pub(crate) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::Gameplay), spawn_scoreboard);
+    app.add_systems(Update, update_scoreboard.run_if(in_state(Screen::Gameplay)));
+}
+
+const SCOREBOARD_KEY: KeyCode = KeyCode::Tab;
+
+#[derive(Component)]
+struct Scoreboard;
+
+fn spawn_scoreboard(mut commands: Commands) {
+    commands
+        .spawn((
+            StateScoped(Screen::Gameplay),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    top: Val::Px(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Scoreboard,
+                TextBundle::from_section("", TextStyle::default())
+                    .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            ));
+        });
+}
+
+fn update_scoreboard(
+    keys: Res<ButtonInput<KeyCode>>,
+    players: Query<(&Player, &Score, Option<&LifetimeStats>), With<Predicted>>,
+    mut q: Query<(&mut Text, &mut Visibility), With<Scoreboard>>,
+) {
+    let Ok((mut text, mut visibility)) = q.get_single_mut() else {
+        return;
+    };
+    if !keys.pressed(SCOREBOARD_KEY) {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Inherited;
+
+    let mut rows: Vec<_> = players.iter().collect();
+    rows.sort_by_key(|(_, score, _)| -score.0);
+    let mut s = format!(
+        "{:<20} {:>6}   {:>6} {:>6} {:>9} {:>9}\n",
+        "Player", "Score", "Kills", "Deaths", "Accuracy", "Played"
+    );
+    for (player, score, lifetime) in rows {
+        let lifetime = lifetime.copied().unwrap_or_default();
+        s.push_str(&format!(
+            "{:<20} {:>6}   {:>6} {:>6} {:>8.0}% {:>7}m\n",
+            player.nickname,
+            score.0,
+            lifetime.kills,
+            lifetime.deaths,
+            lifetime.accuracy() * 100.0,
+            lifetime.playtime_secs / 60,
+        ));
+    }
+    s.push_str("\nKills, deaths, accuracy and time played are across all your games here");
+    text.sections[0].value = s;
+}
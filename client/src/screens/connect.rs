@@ -1,18 +1,27 @@
 use crate::screens::*;
 use bevy::{color::palettes::css, prelude::*};
-use client::NetClient;
+use client::{ClientCommands, NetClient};
 use lightyear::connection::client::ConnectionState;
 use shared::prelude::*;
 
 pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<SelectedShipClass>();
     app.observe(update_connect_status_text_observer);
     app.add_systems(OnEnter(Screen::Connect), spawn_connect_screen);
+    app.add_systems(Update, handle_connection_rejected);
+    app.add_systems(
+        Update,
+        refuse_mismatched_arena.run_if(resource_changed::<CurrentMap>),
+    );
     // systems that only run in Connect state.
     app.add_systems(
         Update,
         (
-            continue_to_gameplay_screen.run_if(connected_to_server),
+            continue_to_gameplay_screen
+                .run_if(connected_to_server)
+                .run_if(not(resource_exists::<ConnectionRejected>)),
             button_system,
+            (ship_class_button_system, highlight_selected_ship_class).chain(),
         )
             .run_if(in_state(Screen::Connect)),
     );
@@ -27,10 +36,70 @@ fn continue_to_gameplay_screen(mut next_screen: ResMut<NextState<Screen>>) {
     next_screen.set(Screen::Gameplay);
 }
 
+/// Exists if the server told us why it won't accept us, until we try connecting again.
+#[derive(Resource, Debug)]
+pub(crate) struct ConnectionRejected(pub RejectReason);
+
+/// Exists if we lost the connection mid-game and couldn't get back in, until we try again.
+#[derive(Resource, Debug)]
+pub(crate) struct ConnectionLost(pub String);
+
+/// The server tells us why it's rejecting us just before it disconnects us, so head back to
+/// the connect screen to show the reason.
+fn handle_connection_rejected(
+    mut events: EventReader<client::MessageEvent<ServerNotice>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        if let ServerNotice::ConnectionRejected(reason) = event.message() {
+            warn!("Connection rejected: {reason}");
+            commands.insert_resource(ConnectionRejected(reason.clone()));
+            commands.trigger(ConnectStatusText(reason.to_string()));
+            next_screen.set(Screen::Connect);
+        }
+    }
+}
+
+/// If the server's arena hash differs from our copy of the arena, this client is from another
+/// build or has different arena files, and would simulate different walls. Treat it like being
+/// out of date.
+fn refuse_mismatched_arena(
+    current_map: Res<CurrentMap>,
+    screen: Res<State<Screen>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut commands: Commands,
+) {
+    if current_map.name.is_empty() {
+        return;
+    }
+    let local_hash = Arena::load(&current_map.name).ok().map(|arena| arena.hash);
+    if local_hash == Some(current_map.hash) {
+        return;
+    }
+    error!(
+        "Arena mismatch! server has '{}' with hash {:x}, ours is {local_hash:x?}",
+        current_map.name, current_map.hash
+    );
+    let reason = RejectReason::OutOfDate;
+    commands.trigger(ConnectStatusText(reason.to_string()));
+    commands.insert_resource(ConnectionRejected(reason));
+    if *screen.get() == Screen::Gameplay {
+        // leaving gameplay disconnects us
+        next_screen.set(Screen::Connect);
+    } else {
+        commands.disconnect_client();
+    }
+}
+
 fn connected_to_server(connection: Res<client::ClientConnection>) -> bool {
     matches!(connection.state(), ConnectionState::Connected)
 }
 
+/// The ship class picked on the connect screen, sent to the server in our `ClientHello`.
+#[derive(Resource, Default, Debug)]
+pub(crate) struct SelectedShipClass(pub ShipClass);
+
 // We need a "Connect Now" button, and a status text to update during connection.
 
 // Marker tag for loading screen components.
@@ -38,17 +107,29 @@ fn connected_to_server(connection: Res<client::ClientConnection>) -> bool {
 struct ConnectUIText;
 #[derive(Component)]
 struct ConnectUIButton;
+#[derive(Component)]
+struct ShipClassButton(ShipClass);
 
 const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::srgb(0.35, 0.75, 0.35);
 
-fn spawn_connect_screen(mut commands: Commands, _asset_server: ResMut<AssetServer>) {
+fn spawn_connect_screen(
+    mut commands: Commands,
+    _asset_server: ResMut<AssetServer>,
+    rejected: Option<Res<ConnectionRejected>>,
+    lost: Option<Res<ConnectionLost>>,
+) {
     info!("spawn_connect_screen");
     let text_style = TextStyle {
         font_size: 30.0,
         ..default()
     };
+    let status = match (rejected, lost) {
+        (Some(rejected), _) => rejected.0.to_string(),
+        (None, Some(lost)) => lost.0.clone(),
+        (None, None) => "Standing By".to_string(),
+    };
 
     commands
         .spawn((
@@ -66,6 +147,51 @@ fn spawn_connect_screen(mut commands: Commands, _asset_server: ResMut<AssetServe
             },
         ))
         .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(10.0),
+                        margin: UiRect {
+                            bottom: Val::Px(20.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for class in ShipClass::ALL {
+                        parent
+                            .spawn((
+                                ShipClassButton(class),
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(100.0),
+                                        height: Val::Px(40.0),
+                                        border: UiRect::all(Val::Px(3.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    border_color: BorderColor(Color::BLACK),
+                                    border_radius: BorderRadius::all(Val::Px(8.0)),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    class.name(),
+                                    TextStyle {
+                                        font_size: 18.0,
+                                        color: Color::srgb(0.9, 0.9, 0.9),
+                                        ..default()
+                                    },
+                                ));
+                            });
+                    }
+                });
+
             parent
                 .spawn((
                     ConnectUIButton,
@@ -103,7 +229,7 @@ fn spawn_connect_screen(mut commands: Commands, _asset_server: ResMut<AssetServe
 
             parent.spawn((
                 ConnectUIText,
-                TextBundle::from_sections([TextSection::new("Standing By", text_style.clone())]),
+                TextBundle::from_sections([TextSection::new(status, text_style.clone())]),
             ));
         });
 }
@@ -160,6 +286,8 @@ fn button_system(
                 *color = PRESSED_BUTTON.into();
                 border_color.0 = css::RED.into();
                 info!("PRESSED");
+                commands.remove_resource::<ConnectionRejected>();
+                commands.remove_resource::<ConnectionLost>();
                 commands.trigger(ConnectStatusText("Connecting to server...".to_string()));
                 commands.trigger(ConnectToServerRequest);
             }
@@ -174,3 +302,34 @@ fn button_system(
         }
     }
 }
+
+fn ship_class_button_system(
+    interaction_query: Query<(&Interaction, &ShipClassButton), Changed<Interaction>>,
+    mut selected: ResMut<SelectedShipClass>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed && selected.0 != button.0 {
+            info!("Selected ship class {:?}", button.0);
+            selected.0 = button.0;
+        }
+    }
+}
+
+fn highlight_selected_ship_class(
+    selected: Res<SelectedShipClass>,
+    mut q: Query<(&ShipClassButton, &mut BorderColor, &mut BackgroundColor)>,
+) {
+    for (button, mut border_color, mut color) in &mut q {
+        let (border, background) = if button.0 == selected.0 {
+            (Color::WHITE, HOVERED_BUTTON)
+        } else {
+            (Color::BLACK, NORMAL_BUTTON)
+        };
+        if border_color.0 != border {
+            border_color.0 = border;
+        }
+        if color.0 != background {
+            color.0 = background;
+        }
+    }
+}
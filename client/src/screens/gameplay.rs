@@ -1,12 +1,36 @@
+use crate::reconnect::{despawn_replicated_entities, ReplicatedEntities};
 use crate::screens::*;
 use bevy::prelude::*;
+use client::{ClientCommands, NetClient};
+use lightyear::connection::client::ConnectionState;
+use shared::prelude::*;
 
 // this mostly exists to clean up state scoped stuff from prior state.
 
 pub(super) fn plugin(app: &mut App) {
     app.add_systems(OnEnter(Screen::Gameplay), enter_gameplay_state);
+    app.add_systems(OnExit(Screen::Gameplay), exit_gameplay_state);
 }
 
 fn enter_gameplay_state() {
     info!("Entering gameplay state");
 }
+
+/// Leaving gameplay, for whatever reason, disconnects us and forgets everything the server told
+/// us, so we can connect to another server from a clean slate.
+fn exit_gameplay_state(
+    connection: Res<client::ClientConnection>,
+    replicated: ReplicatedEntities,
+    mut commands: Commands,
+) {
+    info!("Leaving gameplay state");
+    if !matches!(connection.state(), ConnectionState::Disconnected { .. }) {
+        commands.disconnect_client();
+    }
+    despawn_replicated_entities(&replicated, &mut commands);
+    // replicated resources aren't removed when we disconnect
+    commands.insert_resource(ServerMetadata::default());
+    commands.insert_resource(CurrentMap::default());
+    commands.insert_resource(MatchRules::default());
+    commands.insert_resource(TeamScores::default());
+}
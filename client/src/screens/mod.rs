@@ -1,14 +1,22 @@
 mod connect;
 mod gameplay;
+#[cfg(all(not(feature = "bevygap"), not(target_family = "wasm")))]
+mod server_browser;
 
 use bevy::prelude::*;
-pub(crate) use connect::ConnectToServerRequest;
+pub(crate) use connect::{
+    ConnectToServerRequest, ConnectionLost, ConnectionRejected, SelectedShipClass,
+};
+#[cfg(all(not(feature = "bevygap"), not(target_family = "wasm")))]
+pub(crate) use server_browser::SelectedServer;
 
 pub(super) fn plugin(app: &mut App) {
     app.init_state::<Screen>();
     app.enable_state_scoped_entities::<Screen>();
 
     app.add_plugins((connect::plugin, gameplay::plugin));
+    #[cfg(all(not(feature = "bevygap"), not(target_family = "wasm")))]
+    app.add_plugins(server_browser::plugin);
 }
 
 /// The game's main screen states.
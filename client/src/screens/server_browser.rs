@@ -0,0 +1,387 @@
+//! Server browser for builds without bevygap, shown on the connect screen.
+//!
+//! Lists servers found by LAN discovery, plus any addresses typed in by hand, with each one's
+//! ping, player count, map and location. Click a server to select it, then Connect.
+use crate::screens::*;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use bevy::utils::Instant;
+use shared::prelude::*;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+pub(super) fn plugin(app: &mut App) {
+    app.insert_resource(SelectedServer(SocketAddr::from((
+        [127, 0, 0, 1],
+        SERVER_PORT,
+    ))));
+    app.insert_resource(ServerBrowser::new());
+    app.add_systems(OnEnter(Screen::Connect), spawn_server_browser);
+    app.add_systems(
+        Update,
+        (
+            query_servers.run_if(on_timer(QUERY_INTERVAL)),
+            receive_announcements,
+            type_address,
+            select_server,
+            render_server_list,
+        )
+            .chain()
+            .run_if(in_state(Screen::Connect)),
+    );
+}
+
+/// How often we ask servers for fresh info
+const QUERY_INTERVAL: Duration = Duration::from_secs(2);
+/// Servers that haven't replied for this long are shown as not responding
+const STALE_AFTER: Duration = Duration::from_secs(6);
+
+/// The server the Connect button connects to
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SelectedServer(pub SocketAddr);
+
+#[derive(Debug)]
+struct ServerEntry {
+    /// Where to connect for the game
+    addr: SocketAddr,
+    /// Added by hand, rather than found by LAN discovery
+    manual: bool,
+    announcement: Option<ServerAnnouncement>,
+    ping: Option<Duration>,
+    last_reply: Option<Instant>,
+}
+
+#[derive(Resource)]
+struct ServerBrowser {
+    socket: Option<UdpSocket>,
+    entries: Vec<ServerEntry>,
+    /// Address being typed in
+    address_input: String,
+    /// Error from the last address typed in
+    input_error: Option<String>,
+    /// When we last sent a query, to work out pings
+    queried_at: Option<Instant>,
+}
+
+impl ServerBrowser {
+    fn new() -> Self {
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).and_then(|socket| {
+            socket.set_broadcast(true)?;
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        });
+        let socket = match socket {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                warn!("Server browser can't open a socket, LAN discovery disabled: {e:?}");
+                None
+            }
+        };
+        let mut browser = Self {
+            socket,
+            entries: Vec::new(),
+            address_input: String::new(),
+            input_error: None,
+            queried_at: None,
+        };
+        browser.add_manual(SocketAddr::from(([127, 0, 0, 1], SERVER_PORT)));
+        browser
+    }
+
+    fn add_manual(&mut self, addr: SocketAddr) {
+        if !self.entries.iter().any(|entry| entry.addr == addr) {
+            self.entries.push(ServerEntry {
+                addr,
+                manual: true,
+                announcement: None,
+                ping: None,
+                last_reply: None,
+            });
+        }
+    }
+
+    /// Broadcasts a discovery query, and asks each manually added server directly, since they
+    /// may not be on our network.
+    fn query(&mut self) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        let broadcast = SocketAddr::from(([255, 255, 255, 255], DISCOVERY_PORT));
+        let direct = self
+            .entries
+            .iter()
+            .filter(|entry| entry.manual)
+            .map(|entry| SocketAddr::new(entry.addr.ip(), DISCOVERY_PORT));
+        for target in std::iter::once(broadcast).chain(direct) {
+            if let Err(e) = socket.send_to(DISCOVERY_QUERY, target) {
+                debug!("Discovery query to {target} failed: {e:?}");
+            }
+        }
+        self.queried_at = Some(Instant::now());
+    }
+}
+
+/// Turns typed text into an address, using the default game port if none is given
+fn parse_address(input: &str) -> Result<SocketAddr, String> {
+    let input = input.trim();
+    let with_port = if input.contains(':') {
+        input.to_string()
+    } else {
+        format!("{input}:{SERVER_PORT}")
+    };
+    with_port
+        .to_socket_addrs()
+        .map_err(|e| format!("Bad address '{input}': {e}"))?
+        .find(|addr| addr.is_ipv4())
+        .ok_or_else(|| format!("No IPv4 address for '{input}'"))
+}
+
+#[derive(Component)]
+struct ServerListRoot;
+
+#[derive(Component)]
+struct AddressInputText;
+
+#[derive(Component)]
+struct ServerEntryButton(SocketAddr);
+
+fn spawn_server_browser(mut commands: Commands, mut browser: ResMut<ServerBrowser>) {
+    browser.query();
+    commands
+        .spawn((
+            StateScoped(Screen::Connect),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(20.0),
+                    width: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Servers - type an address and press Enter to add it",
+                TextStyle {
+                    font_size: 18.0,
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                AddressInputText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: Color::srgb(0.9, 0.9, 0.5),
+                        ..default()
+                    },
+                ),
+            ));
+            parent.spawn((
+                ServerListRoot,
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn query_servers(mut browser: ResMut<ServerBrowser>) {
+    browser.query();
+}
+
+fn receive_announcements(mut browser: ResMut<ServerBrowser>) {
+    let browser = browser.as_mut();
+    let Some(socket) = &browser.socket else {
+        return;
+    };
+    let mut buf = [0; 2048];
+    // the socket is non-blocking, so this stops when there's nothing left to read
+    while let Ok((len, from)) = socket.recv_from(&mut buf) {
+        let Some(announcement) = ServerAnnouncement::from_bytes(&buf[..len]) else {
+            continue;
+        };
+        if announcement.protocol_id != PROTOCOL_ID {
+            debug!("Ignoring server at {from} with another protocol");
+            continue;
+        }
+        let addr = SocketAddr::new(from.ip(), announcement.game_port);
+        let now = Instant::now();
+        let ping = browser.queried_at.map(|sent| now - sent);
+        let index = match browser.entries.iter().position(|entry| entry.addr == addr) {
+            Some(index) => index,
+            None => {
+                info!("Found server at {addr}");
+                browser.entries.push(ServerEntry {
+                    addr,
+                    manual: false,
+                    announcement: None,
+                    ping: None,
+                    last_reply: None,
+                });
+                browser.entries.len() - 1
+            }
+        };
+        let entry = &mut browser.entries[index];
+        // broadcast and direct replies can both arrive, keep the first one's ping
+        if entry
+            .last_reply
+            .zip(browser.queried_at)
+            .map_or(true, |(reply, sent)| reply < sent)
+        {
+            entry.ping = ping;
+        }
+        entry.announcement = Some(announcement);
+        entry.last_reply = Some(now);
+    }
+}
+
+fn type_address(
+    mut events: EventReader<KeyboardInput>,
+    mut browser: ResMut<ServerBrowser>,
+    mut selected: ResMut<SelectedServer>,
+) {
+    for event in events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(c)
+                if c.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || ".:-".contains(c)) =>
+            {
+                browser.address_input.push_str(c);
+            }
+            Key::Backspace => {
+                browser.address_input.pop();
+            }
+            Key::Enter if !browser.address_input.is_empty() => {
+                match parse_address(&browser.address_input) {
+                    Ok(addr) => {
+                        info!("Added server {addr}");
+                        browser.add_manual(addr);
+                        browser.address_input.clear();
+                        browser.input_error = None;
+                        selected.0 = addr;
+                        browser.query();
+                    }
+                    Err(e) => browser.input_error = Some(e),
+                }
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn select_server(
+    interaction_query: Query<(&Interaction, &ServerEntryButton), Changed<Interaction>>,
+    mut selected: ResMut<SelectedServer>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            info!("Selected server {}", button.0);
+            selected.0 = button.0;
+        }
+    }
+}
+
+fn describe(entry: &ServerEntry) -> String {
+    let stale = entry
+        .last_reply
+        .map_or(true, |reply| reply.elapsed() > STALE_AFTER);
+    match (&entry.announcement, stale) {
+        (Some(announcement), false) => {
+            let metadata = &announcement.metadata;
+            let mut s = format!(
+                "{}  -  {}ms  -  {}/{} players  -  {}",
+                entry.addr,
+                entry.ping.unwrap_or_default().as_millis(),
+                metadata.players,
+                metadata.max_players,
+                announcement.map,
+            );
+            if !metadata.location.is_empty() {
+                s.push_str(&format!("  -  {}", metadata.location));
+            }
+            s
+        }
+        _ => format!("{}  -  not responding", entry.addr),
+    }
+}
+
+fn render_server_list(
+    browser: Res<ServerBrowser>,
+    selected: Res<SelectedServer>,
+    list: Query<(Entity, Ref<ServerListRoot>)>,
+    mut input_text: Query<&mut Text, With<AddressInputText>>,
+    mut commands: Commands,
+    // rebuilding the list resets hover states, so only do it when something changes
+    mut last_rendered: Local<Vec<String>>,
+) {
+    if let Ok(mut text) = input_text.get_single_mut() {
+        text.sections[0].value = match &browser.input_error {
+            Some(e) => format!("> {}_   ({e})", browser.address_input),
+            None => format!("> {}_", browser.address_input),
+        };
+    }
+    let Ok((list, root)) = list.get_single() else {
+        return;
+    };
+    let rows: Vec<(SocketAddr, String)> = browser
+        .entries
+        .iter()
+        .map(|entry| {
+            let marker = if entry.addr == selected.0 { "> " } else { "  " };
+            (entry.addr, format!("{marker}{}", describe(entry)))
+        })
+        .collect();
+    let labels: Vec<String> = rows.iter().map(|(_, label)| label.clone()).collect();
+    if *last_rendered == labels && !root.is_added() {
+        return;
+    }
+    *last_rendered = labels;
+
+    commands.entity(list).despawn_descendants();
+    commands.entity(list).with_children(|parent| {
+        for (addr, label) in rows {
+            let background = if addr == selected.0 {
+                Color::srgb(0.25, 0.25, 0.25)
+            } else {
+                Color::srgb(0.1, 0.1, 0.1)
+            };
+            parent
+                .spawn((
+                    ServerEntryButton(addr),
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::axes(Val::Px(10.0), Val::Px(4.0)),
+                            ..default()
+                        },
+                        border_radius: BorderRadius::all(Val::Px(4.0)),
+                        background_color: background.into(),
+                        ..default()
+                    },
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                    ));
+                });
+        }
+    });
+}
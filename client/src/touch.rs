@@ -0,0 +1,213 @@
+use crate::aim;
+use crate::screens::Screen;
+use bevy::{color::palettes::css, prelude::*};
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::*};
+use shared::prelude::*;
+
+/// Touchscreen controls: a virtual joystick in the bottom left, and a fire button in the bottom
+/// right. They only appear once the screen has been touched, so desktop players never see them.
+///
+/// Like mouse aiming, the controls write into our `ActionState`: the stick direction is `Aim`,
+/// and how far it's pushed is `Thrust`.
+pub(crate) fn plugin(app: &mut App) {
+    app.init_resource::<TouchControls>();
+    app.add_systems(
+        PreUpdate,
+        apply_touch_controls
+            .after(aim::apply_mouse_aim)
+            .in_set(InputManagerSystem::ManualControl),
+    );
+    app.add_systems(
+        Update,
+        (
+            spawn_touch_controls.run_if(in_state(Screen::Gameplay)),
+            update_touch_controls,
+        )
+            .chain(),
+    );
+}
+
+/// Radius of the joystick base, in logical pixels
+const STICK_RADIUS: f32 = 80.0;
+const KNOB_RADIUS: f32 = 30.0;
+const FIRE_RADIUS: f32 = 50.0;
+/// Distance of the controls from the edges of the screen
+const MARGIN: f32 = 30.0;
+
+#[derive(Resource, Default)]
+struct TouchControls {
+    /// Set by the first touch, and stays on
+    enabled: bool,
+    /// The touch steering the joystick, if any
+    stick_touch: Option<u64>,
+    /// Joystick offset from its centre, from -1 to 1 on each axis, y up
+    stick: Vec2,
+    firing: bool,
+}
+
+#[derive(Component)]
+struct TouchControlsRoot;
+
+#[derive(Component)]
+struct StickKnob;
+
+#[derive(Component)]
+struct FireButton;
+
+/// Centres of the joystick and the fire button, in window coordinates (y down)
+fn control_centres(window: &Window) -> (Vec2, Vec2) {
+    let (width, height) = (window.width(), window.height());
+    let stick = Vec2::new(MARGIN + STICK_RADIUS, height - MARGIN - STICK_RADIUS);
+    let fire = Vec2::new(width - MARGIN - FIRE_RADIUS, height - MARGIN - FIRE_RADIUS);
+    (stick, fire)
+}
+
+fn apply_touch_controls(
+    mut controls: ResMut<TouchControls>,
+    touches: Res<Touches>,
+    windows: Query<&Window>,
+    mut q: Query<&mut ActionState<PlayerActions>, (With<Controlled>, With<Predicted>)>,
+) {
+    if touches.iter_just_pressed().next().is_some() && !controls.enabled {
+        info!("Touch detected, enabling touch controls");
+        controls.enabled = true;
+    }
+    if !controls.enabled {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let (stick_centre, fire_centre) = control_centres(window);
+
+    // a touch that starts on the left half of the screen grabs the joystick, so you don't have
+    // to hit the base exactly
+    if controls.stick_touch.is_none() {
+        controls.stick_touch = touches
+            .iter_just_pressed()
+            .find(|touch| touch.start_position().x < window.width() / 2.0)
+            .map(|touch| touch.id());
+    }
+    controls.stick = match controls.stick_touch.and_then(|id| touches.get_pressed(id)) {
+        Some(touch) => {
+            let offset = (touch.position() - stick_centre) / STICK_RADIUS;
+            Vec2::new(offset.x, -offset.y).clamp_length_max(1.0)
+        }
+        None => {
+            controls.stick_touch = None;
+            Vec2::ZERO
+        }
+    };
+    controls.firing = touches
+        .iter()
+        .any(|touch| touch.position().distance(fire_centre) < FIRE_RADIUS * 1.2);
+
+    let Ok(mut action) = q.get_single_mut() else {
+        return;
+    };
+    // the menu is open
+    if action.disabled() {
+        return;
+    }
+    let push = controls.stick.length();
+    if push > AIM_DEADZONE {
+        action.set_axis_pair(&PlayerActions::Aim, controls.stick / push);
+        action.set_value(
+            &PlayerActions::Thrust,
+            (push - AIM_DEADZONE) / (1.0 - AIM_DEADZONE),
+        );
+    }
+    if controls.firing {
+        action.press(&PlayerActions::Fire);
+    }
+}
+
+fn spawn_touch_controls(
+    controls: Res<TouchControls>,
+    existing: Query<(), With<TouchControlsRoot>>,
+    mut commands: Commands,
+) {
+    if !controls.enabled || !existing.is_empty() {
+        return;
+    }
+    let circle = |radius: f32| Style {
+        position_type: PositionType::Absolute,
+        width: Val::Px(radius * 2.0),
+        height: Val::Px(radius * 2.0),
+        ..default()
+    };
+    commands
+        .spawn((
+            TouchControlsRoot,
+            StateScoped(Screen::Gameplay),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        left: Val::Px(MARGIN),
+                        bottom: Val::Px(MARGIN),
+                        ..circle(STICK_RADIUS)
+                    },
+                    background_color: Color::srgba(1.0, 1.0, 1.0, 0.1).into(),
+                    border_radius: BorderRadius::MAX,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        StickKnob,
+                        NodeBundle {
+                            style: Style {
+                                left: Val::Px(STICK_RADIUS - KNOB_RADIUS),
+                                top: Val::Px(STICK_RADIUS - KNOB_RADIUS),
+                                ..circle(KNOB_RADIUS)
+                            },
+                            background_color: Color::srgba(1.0, 1.0, 1.0, 0.3).into(),
+                            border_radius: BorderRadius::MAX,
+                            ..default()
+                        },
+                    ));
+                });
+            parent.spawn((
+                FireButton,
+                NodeBundle {
+                    style: Style {
+                        right: Val::Px(MARGIN),
+                        bottom: Val::Px(MARGIN),
+                        ..circle(FIRE_RADIUS)
+                    },
+                    background_color: css::RED.with_alpha(0.2).into(),
+                    border_radius: BorderRadius::MAX,
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn update_touch_controls(
+    controls: Res<TouchControls>,
+    mut knob_q: Query<&mut Style, With<StickKnob>>,
+    mut fire_q: Query<&mut BackgroundColor, With<FireButton>>,
+) {
+    if !controls.is_changed() {
+        return;
+    }
+    if let Ok(mut style) = knob_q.get_single_mut() {
+        let offset = controls.stick * (STICK_RADIUS - KNOB_RADIUS);
+        style.left = Val::Px(STICK_RADIUS - KNOB_RADIUS + offset.x);
+        style.top = Val::Px(STICK_RADIUS - KNOB_RADIUS - offset.y);
+    }
+    if let Ok(mut color) = fire_q.get_single_mut() {
+        let alpha = if controls.firing { 0.5 } else { 0.2 };
+        *color = css::RED.with_alpha(alpha).into();
+    }
+}
@@ -0,0 +1,113 @@
+//! Destructible asteroids. Only the server decides when an asteroid breaks, the fragments are
+//! replicated and predicted like balls.
+use bevy::prelude::*;
+use shared::prelude::*;
+
+/// Delay before a new wave of asteroids once they've all been destroyed
+const WAVE_DELAY: Duration = Duration::from_secs(5);
+/// Speed the fragments fly apart at, on top of the parent asteroid's velocity
+const FRAGMENT_SPLIT_SPEED: f32 = 40.0;
+/// Asteroids in a new wave drift at this speed
+const DRIFT_SPEED: f32 = 20.0;
+
+pub struct AsteroidsPlugin;
+
+impl Plugin for AsteroidsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AsteroidWave>();
+        app.add_systems(
+            FixedUpdate,
+            damage_asteroids
+                .run_if(on_event::<BulletHitEvent>())
+                .after(process_collisions),
+        );
+        app.add_systems(Update, respawn_asteroid_wave);
+    }
+}
+
+#[derive(Resource)]
+struct AsteroidWave(Timer);
+
+impl Default for AsteroidWave {
+    fn default() -> Self {
+        Self(Timer::new(WAVE_DELAY, TimerMode::Once))
+    }
+}
+
+/// Spawns the arena's destructible asteroids, drifting in different directions.
+pub fn spawn_asteroids(commands: &mut Commands, arena: &Arena) {
+    for (i, asteroid) in arena.def.destructible_asteroids.iter().enumerate() {
+        // golden angle, so the directions are spread out without needing an rng
+        let velocity = Vec2::from_angle(i as f32 * 2.4) * DRIFT_SPEED;
+        commands.spawn(AsteroidBundle::new(
+            asteroid.radius,
+            asteroid.position,
+            velocity,
+        ));
+    }
+}
+
+fn respawn_asteroid_wave(
+    mut wave: ResMut<AsteroidWave>,
+    asteroids: Query<(), With<Asteroid>>,
+    arena: Res<Arena>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    if !asteroids.is_empty() || arena.def.destructible_asteroids.is_empty() {
+        wave.0.reset();
+        return;
+    }
+    if wave.0.tick(time.delta()).just_finished() {
+        info!("Spawning a new wave of asteroids");
+        spawn_asteroids(&mut commands, &arena);
+    }
+}
+
+fn damage_asteroids(
+    mut events: EventReader<BulletHitEvent>,
+    mut asteroid_q: Query<(&mut Asteroid, &Position, &LinearVelocity)>,
+    mut player_q: Query<(&Player, &mut Score)>,
+    rules: Res<MatchRules>,
+    mut commands: Commands,
+) {
+    for ev in events.read() {
+        let Ok((mut asteroid, position, velocity)) = asteroid_q.get_mut(ev.target) else {
+            continue;
+        };
+        // several bullets can hit in the same tick, but it only breaks once
+        if asteroid.health == 0 {
+            continue;
+        }
+        asteroid.health -= 1;
+        if asteroid.health > 0 {
+            continue;
+        }
+        commands.entity(ev.target).despawn_recursive();
+
+        if rules.mode.shots_score() {
+            if let Some((_, mut score)) = player_q
+                .iter_mut()
+                .find(|(player, _)| player.client_id == ev.bullet_owner)
+            {
+                score.0 += 1;
+            }
+        }
+
+        let Some(fragment_radius) = asteroid.fragment_radius() else {
+            continue;
+        };
+        // fragments fly apart perpendicular to the direction the bullet came from
+        let away = (position.0 - ev.position)
+            .try_normalize()
+            .unwrap_or(Vec2::X);
+        let split = away.perp();
+        for side in [-1.0, 1.0] {
+            commands.spawn(AsteroidBundle::new(
+                fragment_radius,
+                position.0 + split * side * fragment_radius,
+                velocity.0 + split * side * FRAGMENT_SPLIT_SPEED,
+            ));
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Server-initiated disconnects.
+//!
+//! If we disconnect a client in the same frame we send them a message explaining why, the
+//! message never gets sent. So we queue the disconnect, and do it once a short delay has passed.
+use bevy::prelude::*;
+use lightyear::connection::server::ServerConnections;
+use shared::prelude::*;
+
+/// Long enough for a final message to make it out before the connection is closed.
+pub const DISCONNECT_DELAY: Duration = Duration::from_millis(500);
+
+pub struct DisconnectPlugin;
+
+impl Plugin for DisconnectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingDisconnects>();
+        app.add_systems(Update, process_pending_disconnects);
+    }
+}
+
+/// Clients we are going to disconnect, once their timer has finished.
+#[derive(Resource, Default, Debug)]
+pub struct PendingDisconnects(Vec<(ClientId, Timer)>);
+
+impl PendingDisconnects {
+    /// Queue up a disconnect after the default [`DISCONNECT_DELAY`].
+    pub fn disconnect(&mut self, client_id: ClientId) {
+        self.disconnect_after(client_id, DISCONNECT_DELAY);
+    }
+
+    pub fn disconnect_after(&mut self, client_id: ClientId, delay: Duration) {
+        if self.contains(client_id) {
+            return;
+        }
+        self.0.push((client_id, Timer::new(delay, TimerMode::Once)));
+    }
+
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.0.iter().any(|(id, _)| *id == client_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn process_pending_disconnects(
+    mut pending: ResMut<PendingDisconnects>,
+    mut connections: ResMut<ServerConnections>,
+    time: Res<Time>,
+) {
+    pending.0.retain_mut(|(client_id, timer)| {
+        if !timer.tick(time.delta()).finished() {
+            return true;
+        }
+        info!("Disconnecting client {client_id:?}");
+        if let Err(e) = connections.disconnect(*client_id) {
+            warn!("Failed to disconnect client {client_id:?}: {e:?}");
+        }
+        false
+    });
+}
@@ -0,0 +1,71 @@
+//! Answers LAN discovery queries from the client's server browser, on its own thread.
+//!
+//! Only used without bevygap, where players pick a server themselves. The bevy app keeps a
+//! [`ServerAnnouncement`] up to date, and the discovery thread replies with it.
+use bevy::prelude::*;
+use shared::prelude::*;
+use std::net::UdpSocket;
+use std::sync::{Arc, RwLock};
+
+pub struct LanDiscoveryPlugin;
+
+impl Plugin for LanDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        let announcement = Announcement::default();
+        app.insert_resource(announcement.clone());
+        app.add_systems(
+            Update,
+            update_announcement
+                .run_if(resource_changed::<ServerMetadata>.or_else(resource_changed::<CurrentMap>)),
+        );
+
+        let socket = match UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!("Failed to bind LAN discovery to port {DISCOVERY_PORT}: {e:?}");
+                return;
+            }
+        };
+        info!("Answering LAN discovery queries on port {DISCOVERY_PORT}");
+
+        std::thread::Builder::new()
+            .name("lan-discovery".to_string())
+            .spawn(move || {
+                let mut buf = [0; 64];
+                loop {
+                    let (len, from) = match socket.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(e) => {
+                            warn!("LAN discovery receive failed: {e:?}");
+                            continue;
+                        }
+                    };
+                    if &buf[..len] != DISCOVERY_QUERY {
+                        continue;
+                    }
+                    let reply = announcement.0.read().unwrap().to_bytes();
+                    if let Err(e) = socket.send_to(&reply, from) {
+                        debug!("LAN discovery reply to {from} failed: {e:?}");
+                    }
+                }
+            })
+            .expect("Failed to spawn LAN discovery thread");
+    }
+}
+
+/// Written by the bevy app, read by the discovery thread
+#[derive(Resource, Clone, Default)]
+struct Announcement(Arc<RwLock<ServerAnnouncement>>);
+
+fn update_announcement(
+    announcement: Res<Announcement>,
+    metadata: Res<ServerMetadata>,
+    current_map: Res<CurrentMap>,
+) {
+    *announcement.0.write().unwrap() = ServerAnnouncement {
+        protocol_id: PROTOCOL_ID,
+        game_port: SERVER_PORT,
+        map: current_map.name.clone(),
+        metadata: metadata.clone(),
+    };
+}
@@ -0,0 +1,138 @@
+//! Graceful shutdown when the deployment is stopped.
+//!
+//! On SIGTERM (or ctrl-c) we start draining: new connections are refused, connected players
+//! are told the server is going away, and once everyone has left, the current round ends, or the
+//! drain timeout expires, we disconnect any stragglers and exit.
+use crate::disconnect::PendingDisconnects;
+use crate::health::Draining;
+use crate::rotation::RoundEnded;
+use bevy::prelude::*;
+use lightyear::prelude::server::*;
+use lightyear::server::connection::ConnectionManager;
+use shared::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How long to wait for players before shutting down, override with `DRAIN_TIMEOUT_SECS` env.
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u32 = 30;
+
+pub struct DrainPlugin;
+
+impl Plugin for DrainPlugin {
+    fn build(&self, app: &mut App) {
+        let signal = ShutdownSignal::default();
+        let handler_signal = signal.clone();
+        if let Err(e) = ctrlc::set_handler(move || {
+            if handler_signal.0.swap(true, Ordering::SeqCst) {
+                // second signal, stop waiting around.
+                std::process::exit(1);
+            }
+        }) {
+            error!("Failed to install SIGTERM handler: {e:?}");
+        }
+        app.insert_resource(signal);
+
+        let timeout_secs = std::env::var("DRAIN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+        app.insert_resource(DrainConfig { timeout_secs });
+
+        app.add_systems(
+            Update,
+            (
+                start_draining.run_if(not(resource_exists::<DrainTimer>)),
+                progress_draining.run_if(resource_exists::<DrainTimer>),
+            ),
+        );
+    }
+}
+
+/// Set from the signal handler thread
+#[derive(Resource, Clone, Default)]
+struct ShutdownSignal(Arc<AtomicBool>);
+
+#[derive(Resource, Debug)]
+struct DrainConfig {
+    timeout_secs: u32,
+}
+
+/// Exists once draining has begun
+#[derive(Resource, Debug)]
+struct DrainTimer {
+    timer: Timer,
+    /// Set once we've started disconnecting everyone
+    disconnecting: bool,
+}
+
+fn start_draining(
+    signal: Res<ShutdownSignal>,
+    config: Res<DrainConfig>,
+    mut draining: ResMut<Draining>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut commands: Commands,
+) {
+    if !signal.0.load(Ordering::SeqCst) {
+        return;
+    }
+    info!(
+        "Shutdown requested, draining for up to {}s",
+        config.timeout_secs
+    );
+    draining.0 = true;
+    commands.insert_resource(DrainTimer {
+        timer: Timer::new(
+            Duration::from_secs(config.timeout_secs as u64),
+            TimerMode::Once,
+        ),
+        disconnecting: false,
+    });
+    let mut notice = ServerNotice::ShuttingDown {
+        seconds: config.timeout_secs,
+    };
+    if let Err(e) = connection_manager
+        .send_message_to_target::<NoticeChannel, _>(&mut notice, NetworkTarget::All)
+    {
+        warn!("Failed to send shutdown notice: {e:?}");
+    }
+}
+
+fn progress_draining(
+    mut drain: ResMut<DrainTimer>,
+    time: Res<Time>,
+    players: Query<&Player>,
+    mut round_ended: EventReader<RoundEnded>,
+    mut pending: ResMut<PendingDisconnects>,
+    mut exit: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    drain.timer.tick(time.delta());
+
+    if drain.disconnecting {
+        // wait for the queued disconnects to be processed, so clients get a clean disconnect.
+        if pending.is_empty() {
+            info!("Drain complete, exiting");
+            commands.stop_server();
+            exit.send(AppExit::Success);
+        }
+        return;
+    }
+
+    if drain.is_added() {
+        // a fresh reader still sees events from just before draining began, and the round
+        // those ended isn't the one we're waiting for.
+        round_ended.clear();
+    }
+    // no point making players start a round they won't get to finish
+    let round_over = round_ended.read().count() > 0;
+    if players.is_empty() || round_over || drain.timer.finished() {
+        info!(
+            "Drain finished with {} players remaining, disconnecting them",
+            players.iter().count()
+        );
+        for player in players.iter() {
+            pending.disconnect(player.client_id);
+        }
+        drain.disconnecting = true;
+    }
+}
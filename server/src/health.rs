@@ -0,0 +1,201 @@
+//! Liveness and readiness probes for container orchestration, served by the status http server.
+//!
+//! * `/healthz` - liveness: is the main loop still running?
+//! * `/readyz`  - readiness: should we be sent new players?
+use crate::http::HttpState;
+use bevy::prelude::*;
+use bevy::utils::Instant;
+#[cfg(feature = "bevygap")]
+use bevygap_server_plugin::prelude::*;
+use lightyear::prelude::server::NetworkingState;
+use shared::prelude::*;
+
+/// If the main loop hasn't updated the health status for this long, we are considered dead.
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(10);
+/// If the fixed update schedule falls this far behind real time, we report as not ready.
+const MAX_FIXED_UPDATE_LAG: Duration = Duration::from_millis(100);
+/// How long time dropped by a stall keeps counting as lag
+const LAG_WINDOW: Duration = Duration::from_secs(5);
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Draining>();
+        app.add_systems(Last, update_health_status);
+        #[cfg(feature = "bevygap")]
+        app.observe(on_bevygap_ready);
+    }
+}
+
+/// When true, the server is draining (eg: shutting down), and shouldn't be sent new players.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Draining(pub bool);
+
+/// Snapshot of health information, written by the bevy app and read by the http thread.
+#[derive(Debug, Clone)]
+pub struct HealthStatus {
+    /// Last time the main loop wrote to this status
+    pub heartbeat: Instant,
+    /// Always true when built without the bevygap feature
+    pub bevygap_ready: bool,
+    /// Is the netcode server listening for connections
+    pub listening: bool,
+    /// How far the fixed update schedule is lagging behind
+    pub fixed_update_lag: Duration,
+    pub draining: bool,
+}
+
+impl Default for HealthStatus {
+    fn default() -> Self {
+        Self {
+            heartbeat: Instant::now(),
+            bevygap_ready: !cfg!(feature = "bevygap"),
+            listening: false,
+            fixed_update_lag: Duration::ZERO,
+            draining: false,
+        }
+    }
+}
+
+impl HealthStatus {
+    pub fn is_alive(&self) -> bool {
+        self.heartbeat.elapsed() < LIVENESS_TIMEOUT
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.is_alive()
+            && self.bevygap_ready
+            && self.listening
+            && !self.draining
+            && self.fixed_update_lag < MAX_FIXED_UPDATE_LAG
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"alive\":{},\"ready\":{},\"bevygap_ready\":{},\"listening\":{},\"fixed_update_lag_ms\":{},\"draining\":{}}}\n",
+            self.is_alive(),
+            self.is_ready(),
+            self.bevygap_ready,
+            self.listening,
+            self.fixed_update_lag.as_millis(),
+            self.draining,
+        )
+    }
+}
+
+#[cfg(feature = "bevygap")]
+fn on_bevygap_ready(_trigger: Trigger<BevygapReady>, http_state: Res<HttpState>) {
+    http_state.health.write().unwrap().bevygap_ready = true;
+}
+
+/// Measures how far the fixed update schedule is behind real time.
+///
+/// `Time<Fixed>`'s overstep is always under one timestep once the fixed loop has run, so it
+/// can't tell us. Instead, a frame that took longer than a timestep left the fixed schedule
+/// that far behind, and had to catch up. If a stall is longer than `Time<Virtual>`'s max delta,
+/// the excess is dropped for good, so virtual time falls behind real time; that counts as lag
+/// for a [`LAG_WINDOW`] afterwards.
+#[derive(Default, Debug)]
+struct LagTracker {
+    /// Real elapsed time when the current window started
+    window_start: Duration,
+    /// Time dropped by virtual time when the current window started
+    dropped_at_window_start: Duration,
+    /// Time dropped during the previous window
+    dropped_last_window: Duration,
+}
+
+impl LagTracker {
+    fn update(
+        &mut self,
+        real_elapsed: Duration,
+        virtual_elapsed: Duration,
+        frame_time: Duration,
+        timestep: Duration,
+    ) -> Duration {
+        let dropped = real_elapsed.saturating_sub(virtual_elapsed);
+        if real_elapsed.saturating_sub(self.window_start) >= LAG_WINDOW {
+            self.dropped_last_window = dropped.saturating_sub(self.dropped_at_window_start);
+            self.window_start = real_elapsed;
+            self.dropped_at_window_start = dropped;
+        }
+        let dropped_recently = self
+            .dropped_last_window
+            .max(dropped.saturating_sub(self.dropped_at_window_start));
+        dropped_recently + frame_time.saturating_sub(timestep)
+    }
+}
+
+fn update_health_status(
+    http_state: Res<HttpState>,
+    networking_state: Res<State<NetworkingState>>,
+    time_real: Res<Time<Real>>,
+    time_virtual: Res<Time<Virtual>>,
+    time_fixed: Res<Time<Fixed>>,
+    draining: Res<Draining>,
+    mut lag: Local<LagTracker>,
+) {
+    let fixed_update_lag = lag.update(
+        time_real.elapsed(),
+        time_virtual.elapsed(),
+        time_real.delta(),
+        time_fixed.timestep(),
+    );
+    let mut health = http_state.health.write().unwrap();
+    health.heartbeat = Instant::now();
+    health.listening = *networking_state.get() == NetworkingState::Started;
+    health.fixed_update_lag = fixed_update_lag;
+    health.draining = draining.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTEP: Duration = Duration::from_micros(15_625);
+
+    #[test]
+    fn keeping_up_is_not_lag() {
+        let mut tracker = LagTracker::default();
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..1000 {
+            elapsed += TIMESTEP;
+            let lag = tracker.update(elapsed, elapsed, TIMESTEP, TIMESTEP);
+            assert_eq!(lag, Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn slow_frame_is_lag() {
+        let mut tracker = LagTracker::default();
+        let frame = Duration::from_millis(200);
+        let lag = tracker.update(frame, frame, frame, TIMESTEP);
+        assert!(lag >= MAX_FIXED_UPDATE_LAG, "{lag:?}");
+    }
+
+    #[test]
+    fn dropped_time_is_lag_for_a_while() {
+        let mut tracker = LagTracker::default();
+        // a 1s stall, of which virtual time only advanced by its 250ms max delta
+        let real = Duration::from_secs(1);
+        let virt = Duration::from_millis(250);
+        let lag = tracker.update(real, virt, real, TIMESTEP);
+        assert!(lag >= MAX_FIXED_UPDATE_LAG, "{lag:?}");
+
+        // still lagging on the next normal frame
+        let lag = tracker.update(real + TIMESTEP, virt + TIMESTEP, TIMESTEP, TIMESTEP);
+        assert!(lag >= MAX_FIXED_UPDATE_LAG, "{lag:?}");
+
+        // but not two windows later
+        let later = real + LAG_WINDOW * 2;
+        tracker.update(later, later - (real - virt), TIMESTEP, TIMESTEP);
+        let lag = tracker.update(
+            later + LAG_WINDOW,
+            later + LAG_WINDOW - (real - virt),
+            TIMESTEP,
+            TIMESTEP,
+        );
+        assert_eq!(lag, Duration::ZERO);
+    }
+}
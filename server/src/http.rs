@@ -0,0 +1,134 @@
+//! A tiny blocking HTTP server, running on its own threads, for prometheus scraping and health probes.
+//!
+//! We don't want to pull in a whole async web framework for a couple of plain-text endpoints,
+//! so this just speaks enough HTTP/1.1 to answer simple GETs. The bevy app periodically writes
+//! snapshots into [`HttpState`], and the http thread only ever reads from it.
+use crate::health::HealthStatus;
+use bevy::prelude::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+
+/// Default port for the status http server, override with the `STATUS_HTTP_PORT` env.
+pub const DEFAULT_STATUS_HTTP_PORT: u16 = 9090;
+
+/// How long a client gets to send its request, or read our reply. Requests are tiny, so this
+/// only matters for clients that have stalled.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// State shared between the bevy app and the http thread.
+#[derive(Resource, Clone, Default)]
+pub struct HttpState {
+    /// Prometheus text exposition format, rendered by the metrics systems.
+    pub metrics: Arc<RwLock<String>>,
+    /// Liveness and readiness, see the health module.
+    pub health: Arc<RwLock<HealthStatus>>,
+}
+
+pub struct HttpStatusPlugin;
+
+impl Plugin for HttpStatusPlugin {
+    fn build(&self, app: &mut App) {
+        let state = HttpState::default();
+        app.insert_resource(state.clone());
+
+        let port = std::env::var("STATUS_HTTP_PORT")
+            .ok()
+            .and_then(|s| s.parse::<u16>().ok())
+            .unwrap_or(DEFAULT_STATUS_HTTP_PORT);
+
+        let listener = match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to bind status http server to port {port}: {e:?}");
+                return;
+            }
+        };
+        info!("Status http server listening on port {port}");
+
+        std::thread::Builder::new()
+            .name("status-http".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            // one thread per request, so a slow scraper can't hold up the
+                            // orchestrator's health probes
+                            let state = state.clone();
+                            let spawned = std::thread::Builder::new()
+                                .name("status-http-request".to_string())
+                                .spawn(move || {
+                                    if let Err(e) = handle_request(stream, &state) {
+                                        debug!("Status http request failed: {e:?}");
+                                    }
+                                });
+                            if let Err(e) = spawned {
+                                warn!("Failed to spawn status http request thread: {e:?}");
+                            }
+                        }
+                        Err(e) => warn!("Status http accept failed: {e:?}"),
+                    }
+                }
+            })
+            .expect("Failed to spawn status http thread");
+    }
+}
+
+fn handle_request(stream: TcpStream, state: &HttpState) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+
+    // eg: "GET /metrics HTTP/1.1"
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // we don't care about any headers, but need to consume them before replying.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            state.metrics.read().unwrap().clone(),
+        ),
+        ("GET", "/healthz") => {
+            let health = state.health.read().unwrap();
+            let status = if health.is_alive() {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            (status, "application/json", health.to_json())
+        }
+        ("GET", "/readyz") => {
+            let health = state.health.read().unwrap();
+            let status = if health.is_ready() {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            (status, "application/json", health.to_json())
+        }
+        ("GET", _) => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_string(),
+        ),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
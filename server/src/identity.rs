@@ -0,0 +1,237 @@
+//! Recognising players who come back.
+//!
+//! Clients send a persistent identity token in their [`ClientHello`]. When a player disconnects
+//! we remember their nickname, colour and score under that token for a grace window. If they
+//! reconnect within the window, their new ship gets them back.
+//!
+//! The identity is also the key for the player's lifetime stats.
+use crate::disconnect::PendingDisconnects;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use lightyear::prelude::server::*;
+use lightyear::server::events::MessageEvent;
+use shared::prelude::*;
+
+/// How long we remember players after they disconnect, override with `RECONNECT_GRACE_SECS` env.
+pub const DEFAULT_RECONNECT_GRACE_SECS: u64 = 120;
+
+pub struct IdentityPlugin;
+
+impl Plugin for IdentityPlugin {
+    fn build(&self, app: &mut App) {
+        let grace_secs = std::env::var("RECONNECT_GRACE_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_RECONNECT_GRACE_SECS);
+        info!("Reconnect grace window: {grace_secs}s");
+        app.insert_resource(ReturningPlayers {
+            grace: Duration::from_secs(grace_secs),
+            ..default()
+        });
+        app.add_systems(
+            Update,
+            (identify_players, remember_departed, snapshot_players).chain(),
+        );
+    }
+}
+
+/// The player's identity: their token from [`ClientHello`], or a fallback based on their client
+/// id if they didn't send a usable one. Server only.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerIdentity(pub String);
+
+/// What we give back to a returning player
+#[derive(Debug, Clone)]
+struct PlayerSnapshot {
+    identity: String,
+    nickname: String,
+    color: Color,
+    score: i32,
+}
+
+impl PlayerSnapshot {
+    /// Gives a returning player back what they had. In team modes they keep the colour of the
+    /// team they were just put on, which may not be the one they were on before.
+    fn restore(
+        self,
+        player: &mut Player,
+        color: &mut ColorComponent,
+        score: &mut Score,
+        rules: &MatchRules,
+    ) {
+        player.nickname = self.nickname;
+        if !rules.mode.has_teams() {
+            color.0 = self.color;
+        }
+        score.0 = self.score;
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+struct ReturningPlayers {
+    grace: Duration,
+    /// Connected players, updated every frame, since their entity may be gone by the time we
+    /// hear that they disconnected
+    connected: HashMap<ClientId, PlayerSnapshot>,
+    /// Disconnected players by identity, with the time they left
+    departed: HashMap<String, (PlayerSnapshot, Duration)>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn identify_players(
+    mut events: EventReader<MessageEvent<ClientHello>>,
+    mut players: Query<(
+        Entity,
+        &mut Player,
+        &mut ColorComponent,
+        &mut Score,
+        Option<&PlayerIdentity>,
+    )>,
+    mut returning: ResMut<ReturningPlayers>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
+    rules: Res<MatchRules>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+        let token = &event.message().identity;
+        let identity = if is_valid_identity(token) {
+            token.clone()
+        } else {
+            info!("Client {client_id:?} sent an unusable identity, using their client id");
+            format!("client-{}", client_id.to_bits())
+        };
+        // if they're still connected as someone else, we probably haven't noticed their old
+        // connection drop yet. The newest connection wins.
+        let previous = players
+            .iter()
+            .find(|(_, player, .., current)| {
+                player.client_id != client_id
+                    && current.is_some_and(|current| current.0 == identity)
+            })
+            .map(|(_, player, ..)| player.client_id);
+        if let Some(previous) = previous {
+            info!("Client {client_id:?} replaces {previous:?}, which has the same identity");
+            pending_disconnects.disconnect(previous);
+            if let Some(snapshot) = returning.connected.remove(&previous) {
+                returning
+                    .departed
+                    .insert(identity.clone(), (snapshot, time.elapsed()));
+            }
+        }
+
+        let Some((entity, mut player, mut color, mut score, current)) = players
+            .iter_mut()
+            .find(|(_, player, ..)| player.client_id == client_id)
+        else {
+            continue;
+        };
+        // only the first hello sets the identity
+        if current.is_some() {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .insert(PlayerIdentity(identity.clone()));
+
+        let now = time.elapsed();
+        let grace = returning.grace;
+        returning
+            .departed
+            .retain(|_, (_, departed_at)| now - *departed_at < grace);
+        if let Some((snapshot, _)) = returning.departed.remove(&identity) {
+            info!(
+                "Client {client_id:?} is {} returning, restoring score {}",
+                snapshot.nickname, snapshot.score
+            );
+            snapshot.restore(&mut player, &mut color, &mut score, &rules);
+        }
+    }
+}
+
+fn snapshot_players(
+    players: Query<(&Player, &PlayerIdentity, &ColorComponent, &Score)>,
+    mut returning: ResMut<ReturningPlayers>,
+) {
+    for (player, identity, color, score) in players.iter() {
+        returning.connected.insert(
+            player.client_id,
+            PlayerSnapshot {
+                identity: identity.0.clone(),
+                nickname: player.nickname.clone(),
+                color: color.0,
+                score: score.0,
+            },
+        );
+    }
+}
+
+fn remember_departed(
+    mut events: EventReader<DisconnectEvent>,
+    mut returning: ResMut<ReturningPlayers>,
+    time: Res<Time>,
+) {
+    for event in events.read() {
+        let Some(snapshot) = returning.connected.remove(&event.client_id) else {
+            continue;
+        };
+        // replaced by a newer connection with the same identity, which is still playing
+        if returning
+            .connected
+            .values()
+            .any(|other| other.identity == snapshot.identity)
+        {
+            continue;
+        }
+        info!(
+            "Remembering {} for {:?} in case they come back",
+            snapshot.nickname, returning.grace
+        );
+        returning
+            .departed
+            .insert(snapshot.identity.clone(), (snapshot, time.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restored(mode: GameMode) -> (Player, ColorComponent, Score) {
+        let snapshot = PlayerSnapshot {
+            identity: "someone".to_string(),
+            nickname: "Ellen Ripley".to_string(),
+            color: Team::Red.color(),
+            score: 7,
+        };
+        let mut player = Player::new(ClientId::Netcode(2), "Neo".to_string());
+        let mut color = ColorComponent(Team::Blue.color());
+        let mut score = Score(0);
+        let rules = MatchRules { mode, ..default() };
+        snapshot.restore(&mut player, &mut color, &mut score, &rules);
+        (player, color, score)
+    }
+
+    #[test]
+    fn free_for_all_restores_colour() {
+        let (player, color, score) = restored(GameMode::FreeForAll);
+        assert_eq!(player.nickname, "Ellen Ripley");
+        assert_eq!(color.0, Team::Red.color());
+        assert_eq!(score.0, 7);
+    }
+
+    #[test]
+    fn team_modes_keep_new_team_colour() {
+        for mode in [
+            GameMode::TeamDeathmatch,
+            GameMode::CaptureTheBall,
+            GameMode::BallSoccer,
+        ] {
+            let (player, color, score) = restored(mode);
+            assert_eq!(player.nickname, "Ellen Ripley");
+            assert_eq!(color.0, Team::Blue.color(), "{mode:?}");
+            assert_eq!(score.0, 7);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Detects players who have stopped touching their controls, warns them, and eventually
+//! disconnects them to free up the slot.
+use crate::disconnect::PendingDisconnects;
+use bevy::prelude::*;
+use lightyear::server::connection::ConnectionManager;
+use shared::prelude::*;
+
+/// Seconds of no input before a warning is sent, override with `IDLE_WARN_SECS` env. 0 disables.
+pub const DEFAULT_IDLE_WARN_SECS: u32 = 120;
+/// Seconds after the warning before kicking, override with `IDLE_KICK_GRACE_SECS` env.
+pub const DEFAULT_IDLE_KICK_GRACE_SECS: u32 = 30;
+/// Analog inputs must move further than this to count as activity, so sensor noise doesn't.
+const AXIS_EPSILON: f32 = 0.01;
+
+pub struct IdlePlugin;
+
+impl Plugin for IdlePlugin {
+    fn build(&self, app: &mut App) {
+        let config = IdleConfig {
+            warn_secs: env_secs("IDLE_WARN_SECS", DEFAULT_IDLE_WARN_SECS),
+            kick_grace_secs: env_secs("IDLE_KICK_GRACE_SECS", DEFAULT_IDLE_KICK_GRACE_SECS),
+        };
+        if config.warn_secs == 0 {
+            info!("Idle detection disabled");
+            return;
+        }
+        info!("Idle detection: {config:?}");
+        app.insert_resource(config);
+        app.add_systems(FixedUpdate, track_idle_players.in_set(FixedSet::PostMain));
+    }
+}
+
+fn env_secs(name: &str, default: u32) -> u32 {
+    std::env::var(name)
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .unwrap_or(default)
+}
+
+#[derive(Resource, Debug)]
+pub struct IdleConfig {
+    pub warn_secs: u32,
+    pub kick_grace_secs: u32,
+}
+
+/// Server-only component on player entities, tracking when they last touched the controls.
+#[derive(Component, Debug)]
+pub struct IdleTracker {
+    /// The last tick the player's inputs changed, or anything was held
+    pub last_active_tick: Tick,
+    /// Number of ticks since the player was last active.
+    /// (Counted separately because tick differences wrap after ~8 minutes)
+    pub idle_ticks: u32,
+    last_pressed: Vec<PlayerActions>,
+    /// Analog inputs, since steering with a stick or the mouse doesn't press anything
+    last_aim: Vec2,
+    last_thrust: f32,
+    warned: bool,
+}
+
+impl IdleTracker {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            last_active_tick: tick,
+            idle_ticks: 0,
+            last_pressed: Vec::new(),
+            last_aim: Vec2::ZERO,
+            last_thrust: 0.0,
+            warned: false,
+        }
+    }
+}
+
+fn track_idle_players(
+    mut q: Query<(&Player, &ActionState<PlayerActions>, &mut IdleTracker)>,
+    config: Res<IdleConfig>,
+    tick_manager: Res<TickManager>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
+) {
+    let tick = tick_manager.tick();
+    let warn_ticks = config.warn_secs * FIXED_TIMESTEP_HZ as u32;
+    let kick_ticks = warn_ticks + config.kick_grace_secs * FIXED_TIMESTEP_HZ as u32;
+
+    for (player, action, mut tracker) in q.iter_mut() {
+        let pressed = action.get_pressed();
+        let aim = action.axis_pair(&PlayerActions::Aim);
+        let thrust = action.value(&PlayerActions::Thrust);
+        let changed = pressed.len() != tracker.last_pressed.len()
+            || pressed.iter().any(|a| !tracker.last_pressed.contains(a))
+            || aim.distance(tracker.last_aim) > AXIS_EPSILON
+            || (thrust - tracker.last_thrust).abs() > AXIS_EPSILON;
+        // holding thrust or fire the whole time is still playing. Aim doesn't count just for
+        // being non-zero, since mouse aim keeps pointing at the cursor while the player is away.
+        let held = !pressed.is_empty() || thrust.abs() > AXIS_EPSILON;
+
+        if changed || held {
+            tracker.last_active_tick = tick;
+            tracker.idle_ticks = 0;
+            tracker.last_pressed = pressed;
+            tracker.last_aim = aim;
+            tracker.last_thrust = thrust;
+            if tracker.warned {
+                tracker.warned = false;
+                let _ = connection_manager
+                    .send_message::<NoticeChannel, _>(player.client_id, &mut ServerNotice::Clear);
+            }
+            continue;
+        }
+
+        tracker.idle_ticks = tracker.idle_ticks.saturating_add(1);
+
+        if tracker.idle_ticks >= kick_ticks {
+            if !pending_disconnects.contains(player.client_id) {
+                info!(
+                    "Kicking idle client {:?}, last active on {:?}",
+                    player.client_id, tracker.last_active_tick
+                );
+                let _ = connection_manager.send_message::<NoticeChannel, _>(
+                    player.client_id,
+                    &mut ServerNotice::IdleKicked,
+                );
+                pending_disconnects.disconnect(player.client_id);
+            }
+        } else if tracker.idle_ticks >= warn_ticks && !tracker.warned {
+            info!("Warning idle client {:?}", player.client_id);
+            tracker.warned = true;
+            let _ = connection_manager.send_message::<NoticeChannel, _>(
+                player.client_id,
+                &mut ServerNotice::IdleWarning {
+                    seconds: config.kick_grace_secs,
+                },
+            );
+        }
+    }
+}
@@ -4,17 +4,23 @@ use lightyear::prelude::server::ServerTransport;
 use lightyear::server::config::ServerConfig;
 use shared::prelude::*;
 
+mod asteroids;
+mod disconnect;
+#[cfg(not(feature = "bevygap"))]
+mod discovery;
+mod drain;
+mod health;
+mod http;
+mod identity;
+mod idle;
+mod metrics;
+mod modes;
+mod pickups;
+mod player_stats;
+mod rotation;
 mod server_plugin;
 use server_plugin::*;
 
-// This needs to be passed to the matchmaker service as a cli flag too, since it's needed to
-// construct the ConnectTokens.
-// TODO this should be read from ENV or flag or file.. or maybe we deterministically generate it
-//      based on the cert hash, which we already send to the matchmaker?
-pub const PRIVATE_KEY: [u8; PRIVATE_KEY_BYTES] = [
-    1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-];
-
 fn main() {
     let mut app = App::new();
 
@@ -150,10 +156,13 @@ pub fn build_server_netcode_config() -> (server::NetConfig, String) {
         compression: CompressionConfig::None,
     };
 
-    let key = read_lightyear_private_key_from_env().unwrap_or_else(|| {
-        warn!("LIGHTYEAR_PRIVATE_KEY not set, using dummy key");
-        DUMMY_PRIVATE_KEY
-    });
+    let key = match load_private_key() {
+        Ok(key) => key,
+        Err(e) => {
+            error!("Can't load the private key: {e}");
+            std::process::exit(1);
+        }
+    };
 
     // this is to aid debugging, silly to dump it to the logs most of the time.
     // info!("🔐 Using private key: {:?}", key);
@@ -171,3 +180,20 @@ pub fn build_server_netcode_config() -> (server::NetConfig, String) {
         digest_str,
     )
 }
+
+/// The key connect tokens are signed with, which the matchmaker must use too.
+///
+/// Without bevygap there are no connect tokens, and the client uses the zeroed dummy key, so
+/// that's the default. With bevygap, anyone could sign tokens with the dummy key, so we refuse it.
+fn load_private_key() -> Result<[u8; PRIVATE_KEY_BYTES], PrivateKeyError> {
+    let key = read_lightyear_private_key_from_env()?;
+    if cfg!(feature = "bevygap") {
+        return key
+            .ok_or(PrivateKeyError::Missing)
+            .and_then(reject_dummy_key);
+    }
+    Ok(key.unwrap_or_else(|| {
+        warn!("{PRIVATE_KEY_ENV} not set, using dummy key");
+        DUMMY_PRIVATE_KEY
+    }))
+}
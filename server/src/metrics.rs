@@ -0,0 +1,280 @@
+//! Collects server stats and renders them in the prometheus text format, served by the
+//! status http server at `/metrics`.
+use crate::http::HttpState;
+use crate::server_plugin::AwaitingProtocolCheck;
+use bevy::diagnostic::{DiagnosticPath, DiagnosticsPlugin, DiagnosticsStore};
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use bevy::utils::Instant;
+use lightyear::transport::io::IoDiagnosticsPlugin;
+use shared::prelude::*;
+use std::fmt::Write;
+
+pub struct MetricsPlugin;
+
+impl Plugin for MetricsPlugin {
+    fn build(&self, app: &mut App) {
+        // the io byte gauges read lightyear's io diagnostics, which only exist with these plugins.
+        // the gui build already has bevy's through DefaultPlugins, the headless one doesn't.
+        if !app.is_plugin_added::<DiagnosticsPlugin>() {
+            app.add_plugins(DiagnosticsPlugin);
+        }
+        if !app.is_plugin_added::<IoDiagnosticsPlugin>() {
+            app.add_plugins(IoDiagnosticsPlugin);
+        }
+        app.init_resource::<ServerStats>();
+        app.init_resource::<FixedUpdateTiming>();
+        app.add_systems(FixedFirst, fixed_update_started);
+        app.add_systems(FixedLast, fixed_update_finished);
+        app.add_systems(
+            Update,
+            publish_metrics.run_if(on_timer(Duration::from_secs(1))),
+        );
+    }
+}
+
+/// Counters that other server systems bump, for reporting via metrics.
+#[derive(Resource, Default, Debug)]
+pub struct ServerStats {
+    /// Bullets that collided with anything
+    pub bullet_hits: u64,
+    /// Bullets that collided with a player
+    pub bullet_player_hits: u64,
+    /// Total client connections accepted since startup
+    pub connections: u64,
+}
+
+/// Measures how long the FixedUpdate schedule takes to run, ie the cost of simulating a tick.
+#[derive(Resource, Default, Debug)]
+pub struct FixedUpdateTiming {
+    started: Option<Instant>,
+    /// Duration of the most recent tick
+    pub last: Duration,
+    /// Longest tick since metrics were last published
+    pub max: Duration,
+    /// Ticks simulated since startup. Unlike the lightyear tick, this doesn't wrap around.
+    pub ticks: u64,
+}
+
+fn fixed_update_started(mut timing: ResMut<FixedUpdateTiming>) {
+    timing.started = Some(Instant::now());
+}
+
+fn fixed_update_finished(mut timing: ResMut<FixedUpdateTiming>) {
+    let Some(started) = timing.started.take() else {
+        return;
+    };
+    let elapsed = started.elapsed();
+    timing.last = elapsed;
+    timing.max = timing.max.max(elapsed);
+    timing.ticks += 1;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn publish_metrics(
+    http_state: Res<HttpState>,
+    stats: Res<ServerStats>,
+    mut timing: ResMut<FixedUpdateTiming>,
+    tick_manager: Res<TickManager>,
+    time: Res<Time<Real>>,
+    diagnostics: Option<Res<DiagnosticsStore>>,
+    // players still being checked aren't in the game yet
+    players: Query<(&Player, &Score), Without<AwaitingProtocolCheck>>,
+    bullets: Query<(), With<BulletMarker>>,
+    balls: Query<(), With<BallMarker>>,
+    asteroids: Query<(), With<Asteroid>>,
+    entities: Query<()>,
+) {
+    let mut out = String::new();
+
+    gauge(
+        &mut out,
+        "connected_clients",
+        "Number of connected players",
+        players.iter().count() as f64,
+    );
+    counter(
+        &mut out,
+        "connections_total",
+        "Client connections accepted since startup",
+        stats.connections as f64,
+    );
+    counter(
+        &mut out,
+        "uptime_seconds",
+        "Seconds since the server started",
+        time.elapsed_seconds_f64(),
+    );
+    gauge(
+        &mut out,
+        "tick",
+        "Current server simulation tick, wraps around every 65536 ticks",
+        tick_manager.tick().0 as f64,
+    );
+    counter(
+        &mut out,
+        "ticks_total",
+        "Simulation ticks run since startup",
+        timing.ticks as f64,
+    );
+    gauge(
+        &mut out,
+        "tick_duration_seconds",
+        "Time taken to simulate the most recent FixedUpdate tick",
+        timing.last.as_secs_f64(),
+    );
+    gauge(
+        &mut out,
+        "tick_duration_max_seconds",
+        "Longest FixedUpdate tick since the previous sample",
+        timing.max.as_secs_f64(),
+    );
+    timing.max = Duration::ZERO;
+
+    if let Some(diagnostics) = diagnostics {
+        let io_value = |path: &DiagnosticPath| {
+            diagnostics
+                .get(path)
+                .and_then(|d| d.smoothed())
+                .unwrap_or_default()
+        };
+        gauge(
+            &mut out,
+            "io_bytes_in",
+            "Incoming network traffic, as measured by lightyear's io diagnostics",
+            io_value(&IoDiagnosticsPlugin::BYTES_IN),
+        );
+        gauge(
+            &mut out,
+            "io_bytes_out",
+            "Outgoing network traffic, as measured by lightyear's io diagnostics",
+            io_value(&IoDiagnosticsPlugin::BYTES_OUT),
+        );
+    }
+
+    header(&mut out, "entities", "gauge", "Number of entities, by kind");
+    sample(
+        &mut out,
+        "entities",
+        "kind=\"player\"",
+        players.iter().count() as f64,
+    );
+    sample(
+        &mut out,
+        "entities",
+        "kind=\"bullet\"",
+        bullets.iter().count() as f64,
+    );
+    sample(
+        &mut out,
+        "entities",
+        "kind=\"ball\"",
+        balls.iter().count() as f64,
+    );
+    sample(
+        &mut out,
+        "entities",
+        "kind=\"asteroid\"",
+        asteroids.iter().count() as f64,
+    );
+    sample(
+        &mut out,
+        "entities",
+        "kind=\"all\"",
+        entities.iter().count() as f64,
+    );
+
+    header(
+        &mut out,
+        "bullet_hits_total",
+        "counter",
+        "Bullets that hit something",
+    );
+    sample(
+        &mut out,
+        "bullet_hits_total",
+        "target=\"any\"",
+        stats.bullet_hits as f64,
+    );
+    sample(
+        &mut out,
+        "bullet_hits_total",
+        "target=\"player\"",
+        stats.bullet_player_hits as f64,
+    );
+
+    // rtt and jitter are refreshed on the Player component by `update_player_metrics`
+    header(
+        &mut out,
+        "client_rtt_seconds",
+        "gauge",
+        "Round trip time per client",
+    );
+    for (player, _) in players.iter() {
+        sample(
+            &mut out,
+            "client_rtt_seconds",
+            &client_labels(player),
+            player.rtt.as_secs_f64(),
+        );
+    }
+    header(
+        &mut out,
+        "client_jitter_seconds",
+        "gauge",
+        "Jitter per client",
+    );
+    for (player, _) in players.iter() {
+        sample(
+            &mut out,
+            "client_jitter_seconds",
+            &client_labels(player),
+            player.jitter.as_secs_f64(),
+        );
+    }
+    header(&mut out, "client_score", "gauge", "Score per client");
+    for (player, score) in players.iter() {
+        sample(
+            &mut out,
+            "client_score",
+            &client_labels(player),
+            score.0 as f64,
+        );
+    }
+
+    *http_state.metrics.write().unwrap() = out;
+}
+
+/// All metric names get this prefix
+const METRIC_PREFIX: &str = "spaceships_";
+
+fn client_labels(player: &Player) -> String {
+    format!(
+        "client_id=\"{}\",nickname=\"{}\"",
+        player.client_id.to_bits(),
+        player.nickname.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {METRIC_PREFIX}{name} {help}");
+    let _ = writeln!(out, "# TYPE {METRIC_PREFIX}{name} {kind}");
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: f64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{METRIC_PREFIX}{name} {value}");
+    } else {
+        let _ = writeln!(out, "{METRIC_PREFIX}{name}{{{labels}}} {value}");
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    header(out, name, "gauge", help);
+    sample(out, name, "", value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: f64) {
+    header(out, name, "counter", help);
+    sample(out, name, "", value);
+}
@@ -0,0 +1,212 @@
+//! Game modes: free-for-all, team deathmatch, capture the ball, and ball soccer.
+//!
+//! The rules are replicated to clients as [`MatchRules`], so they can predict which bullets
+//! pass through teammates. Team scores are server-authoritative and replicated as [`TeamScores`].
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+use lightyear::prelude::server::*;
+use lightyear::server::connection::ConnectionManager;
+use shared::prelude::*;
+
+/// Team points for pushing a ball into the other team's goal in capture the ball
+pub const CAPTURE_POINTS: i32 = 5;
+/// Ball soccer is played with a single ball of this size, kicked off from the centre
+pub const SOCCER_BALL_RADIUS: f32 = 20.0;
+
+pub struct GameModePlugin;
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        let mode = match std::env::var("GAME_MODE").as_deref() {
+            Err(_) | Ok("ffa") => GameMode::FreeForAll,
+            Ok("tdm") => GameMode::TeamDeathmatch,
+            Ok("ctb") => GameMode::CaptureTheBall,
+            Ok("soccer") => GameMode::BallSoccer,
+            Ok(other) => {
+                panic!("Unknown GAME_MODE '{other}', expected one of: ffa, tdm, ctb, soccer")
+            }
+        };
+        let friendly_fire = std::env::var("FRIENDLY_FIRE").is_ok_and(|s| s == "1" || s == "true");
+        let rules = MatchRules {
+            mode,
+            friendly_fire,
+        };
+        info!("Match rules: {rules:?}");
+        app.insert_resource(rules);
+        app.add_systems(Startup, replicate_match_rules);
+        app.add_systems(
+            Update,
+            replicate_team_scores.run_if(resource_changed::<TeamScores>),
+        );
+        if mode.uses_goals() {
+            app.add_systems(
+                FixedUpdate,
+                (track_ball_touches, score_goals)
+                    .chain()
+                    .in_set(FixedSet::MainCouldDespawn)
+                    .after(FixedSet::Main),
+            );
+        }
+    }
+}
+
+/// Where a ball goes back to after it's been scored. Server only.
+#[derive(Component, Debug)]
+pub struct BallHome(pub Vec2);
+
+/// The last player to touch a ball, with their ship or a bullet. Server only.
+#[derive(Component, Debug)]
+pub struct LastTouchedBy(pub ClientId);
+
+/// The balls for the arena, or a single ball at the centre for ball soccer.
+pub fn spawn_balls(commands: &mut Commands, arena: &Arena, rules: &MatchRules) {
+    let balls = if rules.mode == GameMode::BallSoccer {
+        vec![CircleDef {
+            position: Vec2::ZERO,
+            radius: SOCCER_BALL_RADIUS,
+        }]
+    } else {
+        arena.def.balls.clone()
+    };
+    for ball in balls {
+        commands.spawn((
+            BallBundle::new(ball.radius, ball.position, css::GOLD.into()),
+            BallHome(ball.position),
+        ));
+    }
+}
+
+fn replicate_match_rules(mut commands: Commands) {
+    commands.replicate_resource::<MatchRules, ResourceChannel>(NetworkTarget::All);
+    commands.replicate_resource::<TeamScores, ResourceChannel>(NetworkTarget::All);
+}
+
+fn replicate_team_scores(scores: Res<TeamScores>, mut commands: Commands) {
+    info!("Team scores: {scores:?}");
+    commands.replicate_resource::<TeamScores, ResourceChannel>(NetworkTarget::All);
+}
+
+/// Picks the team with fewest players, so teams stay balanced as people join.
+pub fn pick_team<'a>(teams: impl Iterator<Item = &'a Team>) -> Team {
+    let (red, blue) = teams.fold((0, 0), |(red, blue), team| match team {
+        Team::Red => (red + 1, blue),
+        Team::Blue => (red, blue + 1),
+    });
+    if blue < red {
+        Team::Blue
+    } else {
+        Team::Red
+    }
+}
+
+/// Remember who last touched each ball, to credit them if it goes in a goal.
+fn track_ball_touches(
+    mut collision_event_reader: EventReader<Collision>,
+    ball_q: Query<(), With<BallMarker>>,
+    player_q: Query<&Player>,
+    bullet_q: Query<&BulletMarker>,
+    mut commands: Commands,
+) {
+    for Collision(contacts) in collision_event_reader.read() {
+        let (ball_entity, other) = if ball_q.contains(contacts.entity1) {
+            (contacts.entity1, contacts.entity2)
+        } else if ball_q.contains(contacts.entity2) {
+            (contacts.entity2, contacts.entity1)
+        } else {
+            continue;
+        };
+        let toucher = player_q
+            .get(other)
+            .map(|player| player.client_id)
+            .or_else(|_| bullet_q.get(other).map(|bullet| bullet.owner));
+        if let Ok(client_id) = toucher {
+            commands
+                .entity(ball_entity)
+                .insert(LastTouchedBy(client_id));
+        }
+    }
+}
+
+/// Balls touching a goal score for the team attacking it, and go back home.
+#[allow(clippy::type_complexity)]
+fn score_goals(
+    mut collision_event_reader: EventReader<Collision>,
+    goal_q: Query<(&GoalZone, &Position), Without<BallMarker>>,
+    mut ball_q: Query<
+        (
+            &BallHome,
+            Option<&LastTouchedBy>,
+            &mut Position,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+        ),
+        With<BallMarker>,
+    >,
+    mut player_q: Query<(&Player, &Team, &mut Score)>,
+    rules: Res<MatchRules>,
+    mut team_scores: ResMut<TeamScores>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    // the ball is moved home straight away, but might still be reported as touching the goal
+    let mut scored = Vec::new();
+    for Collision(contacts) in collision_event_reader.read() {
+        let (goal_entity, ball_entity) = if goal_q.contains(contacts.entity1) {
+            (contacts.entity1, contacts.entity2)
+        } else if goal_q.contains(contacts.entity2) {
+            (contacts.entity2, contacts.entity1)
+        } else {
+            continue;
+        };
+        if scored.contains(&ball_entity) {
+            continue;
+        }
+        let Ok((goal, goal_position)) = goal_q.get(goal_entity) else {
+            continue;
+        };
+        let Ok((home, last_touched, mut position, mut velocity, mut angular_velocity)) =
+            ball_q.get_mut(ball_entity)
+        else {
+            continue;
+        };
+        scored.push(ball_entity);
+        let scoring_team = goal.team.opponent();
+        let points = match rules.mode {
+            GameMode::CaptureTheBall => CAPTURE_POINTS,
+            _ => 1,
+        };
+        team_scores.add(scoring_team, points);
+
+        // own goals don't get credited to anyone
+        let scorer = last_touched.and_then(|touched| {
+            player_q
+                .iter_mut()
+                .find(|(player, team, _)| player.client_id == touched.0 && **team == scoring_team)
+                .map(|(player, _, mut score)| {
+                    score.0 += 1;
+                    info!(
+                        "{} scored for the {} team",
+                        player.nickname,
+                        scoring_team.name()
+                    );
+                    player.client_id
+                })
+        });
+        if scorer.is_none() {
+            info!("The {} team scored", scoring_team.name());
+        }
+        let mut event = GoalScored {
+            team: scoring_team,
+            scorer,
+            position: goal_position.0,
+        };
+        if let Err(e) = connection_manager
+            .send_message_to_target::<GameEventChannel, _>(&mut event, NetworkTarget::All)
+        {
+            warn!("Failed to send goal event: {e:?}");
+        }
+
+        position.0 = home.0;
+        velocity.0 = Vec2::ZERO;
+        angular_velocity.0 = 0.0;
+    }
+}
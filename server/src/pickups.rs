@@ -0,0 +1,150 @@
+//! Server-authoritative pickups: spawned periodically, collected by flying into them.
+//!
+//! Weapon pickups swap the ship's weapon for a while, powerups add a timed effect component.
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use shared::prelude::*;
+
+/// Maximum number of pickups in the arena at once
+const MAX_PICKUPS: usize = 4;
+/// How long a weapon from a pickup lasts before reverting to the standard weapon
+const WEAPON_PICKUP_TICKS: u16 = FIXED_TIMESTEP_HZ as u16 * 15;
+
+const PICKUP_ROTATION: [PickupKind; 7] = [
+    PickupKind::Weapon(WeaponKind::SpreadShot),
+    PickupKind::Powerup(PowerupKind::Shield),
+    PickupKind::Weapon(WeaponKind::RapidFire),
+    PickupKind::Powerup(PowerupKind::SpeedBoost),
+    PickupKind::Weapon(WeaponKind::ChargedRail),
+    PickupKind::Powerup(PowerupKind::Cloak),
+    PickupKind::Weapon(WeaponKind::HomingMissile),
+];
+
+pub struct PickupsPlugin;
+
+impl Plugin for PickupsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PickupSpawner>();
+        app.add_systems(
+            Update,
+            spawn_pickups.run_if(on_timer(Duration::from_secs(10))),
+        );
+        app.add_systems(
+            FixedUpdate,
+            process_pickup_collisions
+                .in_set(FixedSet::MainCouldDespawn)
+                .after(FixedSet::Main),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (
+                expire_effect::<Shield>,
+                expire_effect::<SpeedBoost>,
+                expire_effect::<Cloak>,
+            )
+                .in_set(FixedSet::PostMain),
+        );
+    }
+}
+
+/// Cycles through the spots and kinds, so pickups vary without needing an rng
+#[derive(Resource, Default)]
+struct PickupSpawner {
+    spawned: usize,
+}
+
+fn spawn_pickups(
+    mut spawner: ResMut<PickupSpawner>,
+    pickups: Query<&Position, With<Pickup>>,
+    players: Query<(), With<Player>>,
+    arena: Res<Arena>,
+    mut commands: Commands,
+) {
+    let spots = &arena.def.pickup_spots;
+    if players.is_empty() || spots.is_empty() || pickups.iter().count() >= MAX_PICKUPS {
+        return;
+    }
+    // pick the next spot that doesn't already have a pickup on it
+    for i in 0..spots.len() {
+        let spot = spots[(spawner.spawned + i) % spots.len()];
+        if pickups
+            .iter()
+            .any(|pos| pos.0.distance(spot) < PICKUP_RADIUS)
+        {
+            continue;
+        }
+        let kind = PICKUP_ROTATION[spawner.spawned % PICKUP_ROTATION.len()];
+        info!("Spawning {kind:?} pickup at {spot}");
+        commands.spawn(PickupBundle::new(kind, spot));
+        spawner.spawned += 1;
+        return;
+    }
+}
+
+/// Like `process_collisions` for bullets, but only the server decides who got a pickup.
+fn process_pickup_collisions(
+    mut collision_event_reader: EventReader<Collision>,
+    pickup_q: Query<&Pickup>,
+    mut player_q: Query<(&Player, &mut Weapon)>,
+    tick_manager: Res<TickManager>,
+    mut commands: Commands,
+) {
+    // a pickup touching two players in one tick only goes to the first one
+    let mut collected = Vec::new();
+    for Collision(contacts) in collision_event_reader.read() {
+        // when A and B collide, it can be reported as one of:
+        // * A collides with B
+        // * B collides with A
+        let (pickup_entity, player_entity) = if pickup_q.contains(contacts.entity1) {
+            (contacts.entity1, contacts.entity2)
+        } else if pickup_q.contains(contacts.entity2) {
+            (contacts.entity2, contacts.entity1)
+        } else {
+            continue;
+        };
+        if collected.contains(&pickup_entity) {
+            continue;
+        }
+        let Ok(pickup) = pickup_q.get(pickup_entity) else {
+            continue;
+        };
+        let Ok((player, mut weapon)) = player_q.get_mut(player_entity) else {
+            continue;
+        };
+        collected.push(pickup_entity);
+        info!("{} collected {:?}", player.nickname, pickup.kind);
+        match pickup.kind {
+            PickupKind::Weapon(kind) => {
+                *weapon = Weapon::temporary(kind, tick_manager.tick(), WEAPON_PICKUP_TICKS);
+            }
+            PickupKind::Powerup(kind) => {
+                let expires_tick = Tick(tick_manager.tick().0.wrapping_add(kind.duration_ticks()));
+                // collecting a powerup you already have resets its timer
+                let mut player_commands = commands.entity(player_entity);
+                match kind {
+                    PowerupKind::Shield => player_commands.insert(Shield::new(expires_tick)),
+                    PowerupKind::SpeedBoost => {
+                        player_commands.insert(SpeedBoost::new(expires_tick))
+                    }
+                    PowerupKind::Cloak => player_commands.insert(Cloak::new(expires_tick)),
+                };
+            }
+        }
+        // this replicates the despawn to clients.
+        commands.entity(pickup_entity).despawn();
+    }
+}
+
+/// Removes powerup effects once they expire. Removal is replicated to clients.
+fn expire_effect<T: TimedEffect>(
+    q: Query<(Entity, &T)>,
+    tick_manager: Res<TickManager>,
+    mut commands: Commands,
+) {
+    let tick = tick_manager.tick();
+    for (entity, effect) in q.iter() {
+        if effect.is_expired(tick) {
+            commands.entity(entity).remove::<T>();
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Lifetime player stats, kept across sessions.
+//!
+//! Each connected player has a session, counting kills, deaths, hits, shots and playtime since
+//! it was last saved. Sessions are saved to the [`StatsStore`] when the player disconnects, when
+//! a round ends, and when the server exits. The running totals are replicated to clients as the
+//! [`LifetimeStats`] component, for the scoreboard.
+//!
+//! Players are keyed by their [`PlayerIdentity`]. Set `STATS_FILE` to choose where the file backend
+//! keeps them, or set it empty to keep stats in memory only.
+use crate::identity::PlayerIdentity;
+use crate::rotation::RoundEnded;
+use crate::server_plugin::handle_hit_event;
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use bevy::utils::HashMap;
+use lightyear::prelude::server::*;
+use shared::prelude::*;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub const DEFAULT_STATS_FILE: &str = "player_stats.ron";
+
+pub struct PlayerStatsPlugin;
+
+impl Plugin for PlayerStatsPlugin {
+    fn build(&self, app: &mut App) {
+        let path = std::env::var("STATS_FILE").unwrap_or(DEFAULT_STATS_FILE.to_string());
+        app.insert_resource(PlayerStatsStore(open_store(&path)));
+        app.init_resource::<StatsSessions>();
+        app.add_systems(
+            FixedUpdate,
+            count_hits
+                .run_if(on_event::<BulletHitEvent>())
+                .after(process_collisions)
+                // before scoring uses up any shield that absorbed the hit
+                .before(handle_hit_event),
+        );
+        app.add_systems(
+            Update,
+            (
+                start_sessions,
+                count_shots,
+                end_sessions,
+                save_sessions.run_if(on_event::<RoundEnded>()),
+                update_lifetime_stats.run_if(on_timer(Duration::from_secs(1))),
+            )
+                .chain(),
+        );
+        app.add_systems(Last, save_sessions.run_if(on_event::<AppExit>()));
+    }
+}
+
+/// The file store at `path`, or memory only if `path` is empty. Losing stats is better than not
+/// starting, so if the file can't be read we log why and keep stats in memory, leaving the file
+/// alone for someone to look at.
+fn open_store(path: &str) -> Box<dyn StatsStore> {
+    if path.is_empty() {
+        info!("Player stats are kept in memory only");
+        return Box::<MemoryStatsStore>::default();
+    }
+    match FileStatsStore::open(path) {
+        Ok(store) => {
+            info!("Player stats file: {path}");
+            Box::new(store)
+        }
+        Err(e) => {
+            error!("Failed to open stats file '{path}', keeping stats in memory only: {e}");
+            Box::<MemoryStatsStore>::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StatsError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+}
+
+impl std::fmt::Display for StatsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatsError::Io(e) => write!(f, "Stats file error: {e}"),
+            StatsError::Parse(e) => write!(f, "Failed to parse stats: {e}"),
+            StatsError::Serialize(e) => write!(f, "Failed to serialize stats: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StatsError {}
+
+/// Somewhere to keep player stats between sessions.
+pub trait StatsStore: Send + Sync + 'static {
+    /// Stats for this identity, or the default if we've never seen them before
+    fn load(&mut self, identity: &str) -> Result<LifetimeStats, StatsError>;
+    /// Replace the stats for this identity. May not be persisted until [`StatsStore::flush`].
+    fn save(&mut self, identity: &str, stats: &LifetimeStats) -> Result<(), StatsError>;
+    fn flush(&mut self) -> Result<(), StatsError> {
+        Ok(())
+    }
+}
+
+/// Forgets everything when the server exits.
+#[derive(Default)]
+pub struct MemoryStatsStore(HashMap<String, LifetimeStats>);
+
+impl StatsStore for MemoryStatsStore {
+    fn load(&mut self, identity: &str) -> Result<LifetimeStats, StatsError> {
+        Ok(self.0.get(identity).copied().unwrap_or_default())
+    }
+
+    fn save(&mut self, identity: &str, stats: &LifetimeStats) -> Result<(), StatsError> {
+        self.0.insert(identity.to_string(), *stats);
+        Ok(())
+    }
+}
+
+/// Keeps every player's stats in a single RON file, which is rewritten on flush.
+pub struct FileStatsStore {
+    path: PathBuf,
+    stats: BTreeMap<String, LifetimeStats>,
+    dirty: bool,
+}
+
+impl FileStatsStore {
+    /// Reads the existing file, if there is one.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, StatsError> {
+        let path = path.into();
+        let stats = match std::fs::read_to_string(&path) {
+            Ok(source) => ron::from_str(&source).map_err(StatsError::Parse)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(StatsError::Io(e)),
+        };
+        Ok(Self {
+            path,
+            stats,
+            dirty: false,
+        })
+    }
+}
+
+impl StatsStore for FileStatsStore {
+    fn load(&mut self, identity: &str) -> Result<LifetimeStats, StatsError> {
+        Ok(self.stats.get(identity).copied().unwrap_or_default())
+    }
+
+    fn save(&mut self, identity: &str, stats: &LifetimeStats) -> Result<(), StatsError> {
+        self.stats.insert(identity.to_string(), *stats);
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StatsError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let source = ron::ser::to_string_pretty(&self.stats, ron::ser::PrettyConfig::default())
+            .map_err(StatsError::Serialize)?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(StatsError::Io)?;
+        }
+        // write then rename, so a crash mid-write doesn't lose everyone's stats
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, source).map_err(StatsError::Io)?;
+        std::fs::rename(&tmp, &self.path).map_err(StatsError::Io)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+pub struct PlayerStatsStore(pub Box<dyn StatsStore>);
+
+/// A connected player's stats since they were last saved
+#[derive(Debug)]
+struct Session {
+    identity: String,
+    /// What the store had last time we loaded or saved
+    stored: LifetimeStats,
+    unsaved: LifetimeStats,
+    /// Playtime up to here is included in `unsaved`
+    counted_until: Duration,
+}
+
+impl Session {
+    /// Moves playtime since we last counted into the unsaved stats
+    fn count_playtime(&mut self, now: Duration) {
+        let secs = (now - self.counted_until).as_secs();
+        self.unsaved.playtime_secs += secs;
+        self.counted_until += Duration::from_secs(secs);
+    }
+
+    fn total(&self) -> LifetimeStats {
+        let mut total = self.stored;
+        total.add(&self.unsaved);
+        total
+    }
+
+    fn save(&mut self, store: &mut dyn StatsStore, now: Duration) {
+        self.count_playtime(now);
+        let total = self.total();
+        match store.save(&self.identity, &total) {
+            Ok(()) => {
+                self.stored = total;
+                self.unsaved = LifetimeStats::default();
+            }
+            Err(e) => error!("Failed to save stats for '{}': {e}", self.identity),
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+struct StatsSessions(HashMap<ClientId, Session>);
+
+impl StatsSessions {
+    fn get_mut(&mut self, client_id: ClientId) -> Option<&mut LifetimeStats> {
+        self.0
+            .get_mut(&client_id)
+            .map(|session| &mut session.unsaved)
+    }
+}
+
+/// Sessions start once we know who the player is, from their hello
+fn start_sessions(
+    new_players: Query<(Entity, &Player, &PlayerIdentity), Added<PlayerIdentity>>,
+    mut sessions: ResMut<StatsSessions>,
+    mut store: ResMut<PlayerStatsStore>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, player, identity) in new_players.iter() {
+        let identity = identity.0.clone();
+        // a newer connection took over from an older one with the same identity. End the old
+        // session now, so what it counted is in the store before we load, and isn't overwritten
+        // by the new session's saves.
+        let replaced: Vec<ClientId> = sessions
+            .0
+            .iter()
+            .filter(|(client_id, session)| {
+                **client_id != player.client_id && session.identity == identity
+            })
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in replaced {
+            if let Some(mut session) = sessions.0.remove(&client_id) {
+                session.save(store.0.as_mut(), time.elapsed());
+            }
+            if let Err(e) = store.0.flush() {
+                error!("Failed to write stats: {e}");
+            }
+        }
+        let stored = store.0.load(&identity).unwrap_or_else(|e| {
+            error!("Failed to load stats for '{identity}': {e}");
+            LifetimeStats::default()
+        });
+        info!("Lifetime stats for {}: {stored:?}", player.nickname);
+        commands.entity(entity).insert(stored);
+        sessions.0.insert(
+            player.client_id,
+            Session {
+                identity,
+                stored,
+                unsaved: LifetimeStats::default(),
+                counted_until: time.elapsed(),
+            },
+        );
+    }
+}
+
+fn end_sessions(
+    mut events: EventReader<DisconnectEvent>,
+    mut sessions: ResMut<StatsSessions>,
+    mut store: ResMut<PlayerStatsStore>,
+    time: Res<Time>,
+) {
+    let mut ended = false;
+    for event in events.read() {
+        if let Some(mut session) = sessions.0.remove(&event.client_id) {
+            session.save(store.0.as_mut(), time.elapsed());
+            ended = true;
+        }
+    }
+    if ended {
+        if let Err(e) = store.0.flush() {
+            error!("Failed to write stats: {e}");
+        }
+    }
+}
+
+/// Saves everyone, at the end of a round and when the server exits
+fn save_sessions(
+    mut sessions: ResMut<StatsSessions>,
+    mut store: ResMut<PlayerStatsStore>,
+    time: Res<Time>,
+) {
+    for session in sessions.0.values_mut() {
+        session.save(store.0.as_mut(), time.elapsed());
+    }
+    if let Err(e) = store.0.flush() {
+        error!("Failed to write stats: {e}");
+    }
+}
+
+fn count_shots(
+    new_bullets: Query<&BulletMarker, Added<BulletMarker>>,
+    mut sessions: ResMut<StatsSessions>,
+) {
+    for bullet in new_bullets.iter() {
+        if let Some(stats) = sessions.get_mut(bullet.owner) {
+            stats.shots += 1;
+        }
+    }
+}
+
+fn count_hits(
+    mut events: EventReader<BulletHitEvent>,
+    walls: Query<(), With<Wall>>,
+    players: Query<(&Player, Has<Shield>, Option<&Team>)>,
+    rules: Res<MatchRules>,
+    mut sessions: ResMut<StatsSessions>,
+) {
+    let find = |client_id: ClientId| {
+        players
+            .iter()
+            .find(|(player, ..)| player.client_id == client_id)
+    };
+    for ev in events.read() {
+        if walls.contains(ev.target) {
+            continue;
+        }
+        if let Some(stats) = sessions.get_mut(ev.bullet_owner) {
+            stats.hits += 1;
+        }
+        let Some(victim) = ev.victim_client_id else {
+            continue;
+        };
+        if victim == ev.bullet_owner {
+            continue;
+        }
+        let Some((_, shielded, victim_team)) = find(victim) else {
+            continue;
+        };
+        let shooter_team = find(ev.bullet_owner).and_then(|(_, _, team)| team);
+        if !counts_as_kill(&rules, shielded, shooter_team, victim_team) {
+            continue;
+        }
+        if let Some(stats) = sessions.get_mut(ev.bullet_owner) {
+            stats.kills += 1;
+        }
+        if let Some(stats) = sessions.get_mut(victim) {
+            stats.deaths += 1;
+        }
+    }
+}
+
+/// Whether shooting a player counts as a kill and a death, following the scoring in
+/// `handle_hit_event`: not in modes where shots don't score, not if a shield absorbed it, and not
+/// when hitting a teammate, which only happens with friendly fire on and costs a point instead.
+fn counts_as_kill(
+    rules: &MatchRules,
+    shielded: bool,
+    shooter_team: Option<&Team>,
+    victim_team: Option<&Team>,
+) -> bool {
+    let teammates = shooter_team.is_some() && shooter_team == victim_team;
+    rules.mode.shots_score() && !shielded && rules.can_hit(shooter_team, victim_team) && !teammates
+}
+
+/// Keeps the replicated totals up to date, including this session
+fn update_lifetime_stats(
+    mut players: Query<(&Player, &mut LifetimeStats)>,
+    mut sessions: ResMut<StatsSessions>,
+    time: Res<Time>,
+) {
+    for (player, mut stats) in players.iter_mut() {
+        let Some(session) = sessions.0.get_mut(&player.client_id) else {
+            continue;
+        };
+        session.count_playtime(time.elapsed());
+        let total = session.total();
+        if *stats != total {
+            *stats = total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test's files
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("player-stats-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn stats(kills: u32, playtime_secs: u64) -> LifetimeStats {
+        LifetimeStats {
+            kills,
+            deaths: 1,
+            hits: 5,
+            shots: 10,
+            playtime_secs,
+        }
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        let path = test_dir("round-trip").join("stats.ron");
+        let mut store = FileStatsStore::open(&path).unwrap();
+        assert_eq!(store.load("a").unwrap(), LifetimeStats::default());
+        store.save("a", &stats(3, 60)).unwrap();
+        store.save("b", &stats(1, 5)).unwrap();
+        store.flush().unwrap();
+
+        let mut reopened = FileStatsStore::open(&path).unwrap();
+        assert_eq!(reopened.load("a").unwrap(), stats(3, 60));
+        assert_eq!(reopened.load("b").unwrap(), stats(1, 5));
+        assert_eq!(reopened.load("c").unwrap(), LifetimeStats::default());
+    }
+
+    #[test]
+    fn flush_replaces_file_via_tmp() {
+        let path = test_dir("tmp").join("stats.ron");
+        let tmp = path.with_extension("tmp");
+        let mut store = FileStatsStore::open(&path).unwrap();
+        // nothing to write yet
+        store.flush().unwrap();
+        assert!(!path.exists());
+
+        store.save("a", &stats(1, 1)).unwrap();
+        // left over from a crash mid-write
+        std::fs::write(&tmp, "garbage").unwrap();
+        store.flush().unwrap();
+        assert!(!tmp.exists());
+        let mut reopened = FileStatsStore::open(&path).unwrap();
+        assert_eq!(reopened.load("a").unwrap(), stats(1, 1));
+    }
+
+    #[test]
+    fn flush_creates_missing_directory() {
+        let path = test_dir("missing").join("nested").join("stats.ron");
+        let mut store = FileStatsStore::open(&path).unwrap();
+        store.save("a", &stats(2, 2)).unwrap();
+        store.flush().unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn corrupt_file_is_an_error() {
+        let path = test_dir("corrupt").join("stats.ron");
+        std::fs::write(&path, "{ not stats").unwrap();
+        assert!(matches!(
+            FileStatsStore::open(&path),
+            Err(StatsError::Parse(_))
+        ));
+        // and the server carries on without it
+        let mut store = open_store(path.to_str().unwrap());
+        store.save("a", &stats(1, 1)).unwrap();
+        assert_eq!(store.load("a").unwrap(), stats(1, 1));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{ not stats");
+    }
+
+    #[test]
+    fn sessions_accumulate() {
+        let mut store = MemoryStatsStore::default();
+        store.save("a", &stats(10, 100)).unwrap();
+        let mut session = Session {
+            identity: "a".to_string(),
+            stored: store.load("a").unwrap(),
+            unsaved: LifetimeStats::default(),
+            counted_until: Duration::from_secs(50),
+        };
+        session.unsaved.kills += 2;
+        session.save(&mut store, Duration::from_millis(80_500));
+        let saved = store.load("a").unwrap();
+        assert_eq!(saved.kills, 12);
+        assert_eq!(saved.playtime_secs, 130);
+        assert_eq!(session.unsaved, LifetimeStats::default());
+
+        // saving again only adds what's new, including the half second left over
+        session.unsaved.kills += 1;
+        session.save(&mut store, Duration::from_millis(90_500));
+        let saved = store.load("a").unwrap();
+        assert_eq!(saved.kills, 13);
+        assert_eq!(saved.playtime_secs, 140);
+    }
+
+    #[test]
+    fn kills_follow_scoring_rules() {
+        let ffa = MatchRules::default();
+        assert!(counts_as_kill(&ffa, false, None, None));
+        assert!(!counts_as_kill(&ffa, true, None, None));
+
+        let mut tdm = MatchRules {
+            mode: GameMode::TeamDeathmatch,
+            ..default()
+        };
+        let (red, blue) = (Some(&Team::Red), Some(&Team::Blue));
+        assert!(counts_as_kill(&tdm, false, red, blue));
+        assert!(!counts_as_kill(&tdm, true, red, blue));
+        assert!(!counts_as_kill(&tdm, false, red, red));
+        tdm.friendly_fire = true;
+        assert!(!counts_as_kill(&tdm, false, red, red));
+
+        let soccer = MatchRules {
+            mode: GameMode::BallSoccer,
+            ..default()
+        };
+        assert!(!counts_as_kill(&soccer, false, red, blue));
+    }
+}
@@ -0,0 +1,159 @@
+//! Map rotation: the server plays each arena in the rotation list for a while, then moves on.
+//!
+//! The arena in play is replicated to clients as the [`CurrentMap`] resource. Clients load the
+//! same arena from their own copy, and the shared plugin rebuilds walls and obstacles.
+use crate::asteroids::spawn_asteroids;
+use crate::modes::spawn_balls;
+use bevy::prelude::*;
+use lightyear::prelude::server::*;
+use lightyear::server::connection::ConnectionManager;
+use shared::prelude::*;
+
+/// Seconds each map is played for, override with `MAP_DURATION_SECS` env.
+pub const DEFAULT_MAP_DURATION_SECS: u32 = 300;
+/// How long before a map change clients are told about it
+const MAP_CHANGE_WARNING_SECS: u32 = 10;
+
+pub struct MapRotationPlugin;
+
+impl Plugin for MapRotationPlugin {
+    fn build(&self, app: &mut App) {
+        // MAP_ROTATION is a comma separated list of arena names. If it's not set, we play
+        // the single arena from ARENA (or the default), with no rotation.
+        let maps: Vec<String> = std::env::var("MAP_ROTATION")
+            .or_else(|_| std::env::var("ARENA"))
+            .unwrap_or(DEFAULT_ARENA.to_string())
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        // fail on startup rather than when the rotation reaches a broken arena
+        let arenas: Vec<Arena> = maps
+            .iter()
+            .map(|name| {
+                Arena::load(name).unwrap_or_else(|e| panic!("Failed to load arena '{name}': {e}"))
+            })
+            .collect();
+        assert!(!arenas.is_empty(), "Map rotation is empty");
+
+        let duration_secs = std::env::var("MAP_DURATION_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_MAP_DURATION_SECS);
+        info!("Map rotation: {maps:?}, {duration_secs}s each");
+
+        let first = arenas[0].clone();
+        app.insert_resource(CurrentMap {
+            name: first.def.name.clone(),
+            hash: first.hash,
+        });
+        app.insert_resource(first);
+        app.add_event::<RoundEnded>();
+        app.add_systems(Startup, replicate_current_map);
+
+        if arenas.len() > 1 && duration_secs > 0 {
+            app.insert_resource(MapRotation {
+                arenas,
+                index: 0,
+                timer: Timer::new(
+                    Duration::from_secs(duration_secs as u64),
+                    TimerMode::Repeating,
+                ),
+                warned: false,
+            });
+            app.add_systems(Update, rotate_maps);
+        }
+    }
+}
+
+/// Sent when a map's time is up, just before the next one starts.
+#[derive(Event, Debug)]
+pub struct RoundEnded;
+
+#[derive(Resource)]
+struct MapRotation {
+    arenas: Vec<Arena>,
+    index: usize,
+    timer: Timer,
+    /// Whether clients have been told about the upcoming map change
+    warned: bool,
+}
+
+impl MapRotation {
+    fn next(&self) -> &Arena {
+        &self.arenas[(self.index + 1) % self.arenas.len()]
+    }
+}
+
+fn replicate_current_map(mut commands: Commands) {
+    commands.replicate_resource::<CurrentMap, ResourceChannel>(NetworkTarget::All);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rotate_maps(
+    mut rotation: ResMut<MapRotation>,
+    time: Res<Time>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut current_map: ResMut<CurrentMap>,
+    mut round_ended: EventWriter<RoundEnded>,
+    mut team_scores: ResMut<TeamScores>,
+    rules: Res<MatchRules>,
+    mut players: Query<(&mut Position, &mut LinearVelocity, &mut AngularVelocity), With<Player>>,
+    to_despawn: Query<
+        Entity,
+        Or<(
+            With<BallMarker>,
+            With<Asteroid>,
+            With<Pickup>,
+            With<BulletMarker>,
+        )>,
+    >,
+    mut commands: Commands,
+) {
+    rotation.timer.tick(time.delta());
+
+    let remaining = rotation.timer.remaining_secs() as u32;
+    if !rotation.warned && remaining < MAP_CHANGE_WARNING_SECS {
+        rotation.warned = true;
+        let mut notice = ServerNotice::MapChanging {
+            name: rotation.next().def.name.clone(),
+            seconds: remaining,
+        };
+        if let Err(e) = connection_manager
+            .send_message_to_target::<NoticeChannel, _>(&mut notice, NetworkTarget::All)
+        {
+            warn!("Failed to send map change notice: {e:?}");
+        }
+    }
+
+    if !rotation.timer.just_finished() {
+        return;
+    }
+    rotation.warned = false;
+    rotation.index = (rotation.index + 1) % rotation.arenas.len();
+    let arena = rotation.arenas[rotation.index].clone();
+    info!("Changing map to '{}'", arena.def.name);
+    round_ended.send(RoundEnded);
+
+    // balls, asteroids, pickups and bullets belong to the old arena. Despawns are replicated.
+    for e in to_despawn.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    spawn_balls(&mut commands, &arena, &rules);
+    spawn_asteroids(&mut commands, &arena);
+    // team scores are per-map, but players keep their ships and own scores, and move to the new arena's spawn points
+    for (n, (mut position, mut velocity, mut angular_velocity)) in players.iter_mut().enumerate() {
+        position.0 = arena.player_spawn(n);
+        velocity.0 = Vec2::ZERO;
+        angular_velocity.0 = 0.0;
+    }
+
+    *team_scores = TeamScores::default();
+    *current_map = CurrentMap {
+        name: arena.def.name.clone(),
+        hash: arena.hash,
+    };
+    commands.replicate_resource::<CurrentMap, ResourceChannel>(NetworkTarget::All);
+    // the shared plugin rebuilds the walls when this changes
+    commands.insert_resource(arena);
+}
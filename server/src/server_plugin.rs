@@ -1,3 +1,17 @@
+use crate::asteroids::{spawn_asteroids, AsteroidsPlugin};
+use crate::disconnect::{DisconnectPlugin, PendingDisconnects};
+#[cfg(not(feature = "bevygap"))]
+use crate::discovery::LanDiscoveryPlugin;
+use crate::drain::DrainPlugin;
+use crate::health::{Draining, HealthPlugin};
+use crate::http::HttpStatusPlugin;
+use crate::identity::IdentityPlugin;
+use crate::idle::{IdlePlugin, IdleTracker};
+use crate::metrics::{MetricsPlugin, ServerStats};
+use crate::modes::{pick_team, spawn_balls, GameModePlugin};
+use crate::pickups::PickupsPlugin;
+use crate::player_stats::PlayerStatsPlugin;
+use crate::rotation::MapRotationPlugin;
 use bevy::color::palettes::css;
 use bevy::prelude::*;
 use bevy::time::common_conditions::on_timer;
@@ -10,6 +24,9 @@ use lightyear::server::connection::ConnectionManager;
 use lightyear::server::events::MessageEvent;
 use shared::prelude::*;
 
+/// Default player limit, override with the `MAX_PLAYERS` env.
+pub const DEFAULT_MAX_PLAYERS: u32 = 12;
+
 #[derive(Default)]
 pub struct BevygapSpaceshipsServerPlugin {
     pub cert_digest: String,
@@ -30,8 +47,37 @@ impl Plugin for BevygapSpaceshipsServerPlugin {
         {
             // without bevygap we just start listening immediately.
             app.add_systems(Startup, start_listening);
+            // and players find us with the client's server browser
+            app.add_plugins(LanDiscoveryPlugin);
         }
 
+        app.add_plugins((
+            HttpStatusPlugin,
+            MetricsPlugin,
+            HealthPlugin,
+            DisconnectPlugin,
+            DrainPlugin,
+            IdlePlugin,
+            PickupsPlugin,
+            MapRotationPlugin,
+            GameModePlugin,
+            AsteroidsPlugin,
+            IdentityPlugin,
+            PlayerStatsPlugin,
+        ));
+
+        let max_players = std::env::var("MAX_PLAYERS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_MAX_PLAYERS);
+        info!("Max players: {max_players}");
+
+        app.insert_resource(ServerMetadata {
+            max_players,
+            ..default()
+        });
+
+        app.add_event::<ProtocolCheckFailed>();
         app.add_systems(Startup, init);
         app.add_systems(
             PreUpdate,
@@ -49,7 +95,16 @@ impl Plugin for BevygapSpaceshipsServerPlugin {
         app.add_systems(
             Update,
             (
-                handle_connections,
+                (handle_connections, update_player_count).chain(),
+                (
+                    check_client_protocol,
+                    expire_protocol_checks,
+                    refuse_failed_protocol_checks,
+                    despawn_unchecked_players,
+                )
+                    .chain()
+                    .after(handle_connections),
+                handle_client_hello.after(handle_connections),
                 update_player_metrics.run_if(on_timer(Duration::from_secs(1))),
             ),
         );
@@ -88,6 +143,25 @@ fn update_server_metadata(
     commands.replicate_resource::<ServerMetadata, ResourceChannel>(NetworkTarget::All);
 }
 
+/// Keeps the player count in `ServerMetadata` current, so clients can see how full we are.
+///
+/// TODO(follow-up): also publish `players`/`max_players` to the matchmaker through the bevygap
+/// server context, so it can avoid sending players to full servers. bevygap_server_plugin
+/// v0.1.7 has no API for servers to report their capacity, so that needs adding to bevygap
+/// first; until then full servers turn players away with `RejectReason::ServerFull`.
+fn update_player_count(
+    mut metadata: ResMut<ServerMetadata>,
+    players: Query<(), With<Player>>,
+    mut commands: Commands,
+) {
+    let players = players.iter().count() as u32;
+    if metadata.players != players {
+        metadata.players = players;
+        info!("Players: {}/{}", metadata.players, metadata.max_players);
+        commands.replicate_resource::<ServerMetadata, ResourceChannel>(NetworkTarget::All);
+    }
+}
+
 /// Since Player is replicated, this allows the clients to display remote players' latency stats.
 fn update_player_metrics(
     connection_manager: Res<ConnectionManager>,
@@ -112,7 +186,7 @@ fn start_listening_once_bevygap_ready(_trigger: Trigger<BevygapReady>, mut comma
     commands.start_server();
 }
 
-fn init(mut commands: Commands) {
+fn init(mut commands: Commands, arena: Res<Arena>, rules: Res<MatchRules>) {
     #[cfg(feature = "gui")]
     {
         commands.spawn(
@@ -130,14 +204,8 @@ fn init(mut commands: Commands) {
             }),
         );
     }
-    // the balls are server-authoritative
-    const NUM_BALLS: usize = 6;
-    for i in 0..NUM_BALLS {
-        let radius = 10.0 + i as f32 * 4.0;
-        let angle: f32 = i as f32 * (TAU / NUM_BALLS as f32);
-        let pos = Vec2::new(125.0 * angle.cos(), 125.0 * angle.sin());
-        commands.spawn(BallBundle::new(radius, pos, css::GOLD.into()));
-    }
+    spawn_balls(&mut commands, &arena, &rules);
+    spawn_asteroids(&mut commands, &arena);
 }
 
 pub(crate) fn replicate_inputs(
@@ -161,30 +229,45 @@ pub(crate) fn replicate_inputs(
 }
 
 /// Whenever a new client connects, spawn their spaceship
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn handle_connections(
     mut connections: EventReader<ConnectEvent>,
     mut commands: Commands,
-    all_players: Query<Entity, With<Player>>,
+    all_players: Query<Option<&Team>, With<Player>>,
+    mut stats: ResMut<ServerStats>,
+    draining: Res<Draining>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    tick_manager: Res<TickManager>,
+    metadata: Res<ServerMetadata>,
+    arena: Res<Arena>,
+    rules: Res<MatchRules>,
 ) {
     // track the number of connected players in order to pick colors and starting positions
     let mut player_n = all_players.iter().count();
+    // and which teams they are on, to keep teams balanced
+    let mut teams: Vec<Team> = all_players.iter().flatten().copied().collect();
     for connection in connections.read() {
         let client_id = connection.client_id;
-        info!("New connected client, client_id: {client_id:?}. Spawning player entity..");
-        // replicate newly connected clients to all players
-        let replicate = Replicate {
-            sync: SyncTarget {
-                prediction: NetworkTarget::All,
-                ..default()
-            },
-            controlled_by: ControlledBy {
-                target: NetworkTarget::Single(client_id),
-                ..default()
-            },
-            // make sure that all entities that are predicted are part of the same replication group
-            group: REPLICATION_GROUP,
-            ..default()
+        let reject_reason = if draining.0 {
+            Some(RejectReason::ShuttingDown)
+        } else if player_n as u32 >= metadata.max_players {
+            Some(RejectReason::ServerFull {
+                max_players: metadata.max_players,
+            })
+        } else {
+            None
         };
+        if let Some(reason) = reject_reason {
+            info!("Refusing client {client_id:?}: {reason}");
+            let _ = connection_manager.send_message::<NoticeChannel, _>(
+                client_id,
+                &mut ServerNotice::ConnectionRejected(reason),
+            );
+            pending_disconnects.disconnect(client_id);
+            continue;
+        }
+        info!("New connected client, client_id: {client_id:?}. Spawning player entity..");
         // pick color and x,y pos for player
 
         let available_colors = [
@@ -201,10 +284,15 @@ pub(crate) fn handle_connections(
             css::WHITE,
             css::RED,
         ];
-        let col = available_colors[player_n % available_colors.len()];
-        let angle: f32 = player_n as f32 * 5.0;
-        let x = 200.0 * angle.cos();
-        let y = 200.0 * angle.sin();
+        let mut col: Color = available_colors[player_n % available_colors.len()].into();
+        let spawn_position = arena.player_spawn(player_n);
+
+        let team = rules.mode.has_teams().then(|| pick_team(teams.iter()));
+        if let Some(team) = team {
+            info!("Client {client_id:?} joins the {} team", team.name());
+            teams.push(team);
+            col = team.color();
+        }
 
         // spawn the player with ActionState - the client will add their own InputMap
         let player_ent = commands
@@ -213,16 +301,172 @@ pub(crate) fn handle_connections(
                 Score(0),
                 Name::new("Player"),
                 ActionState::<PlayerActions>::default(),
-                Position(Vec2::new(x, y)),
-                replicate,
-                PhysicsBundle::player_ship(),
-                Weapon::new((FIXED_TIMESTEP_HZ / 5.0) as u16),
-                ColorComponent(col.into()),
+                Position(spawn_position),
+                // replicated once the client's protocol check passes
+                AwaitingProtocolCheck(Timer::new(PROTOCOL_CHECK_TIMEOUT, TimerMode::Once)),
+                // until their ClientHello arrives, everyone's a fighter
+                ShipClass::default(),
+                ShipClass::default().physics_bundle(),
+                Weapon::new(ShipClass::default().def().weapon),
+                ColorComponent(col),
+                IdleTracker::new(tick_manager.tick()),
             ))
             .id();
+        if let Some(team) = team {
+            commands.entity(player_ent).insert(team);
+        }
 
         info!("Created entity {player_ent:?} for client {client_id:?}");
         player_n += 1;
+        stats.connections += 1;
+    }
+}
+
+/// How long a new client has to send its [`ProtocolCheck`]. Clients too old to send one are
+/// turned away once it passes.
+const PROTOCOL_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// On a player who hasn't passed the protocol check yet. They aren't replicated until they
+/// have, since an out of date client may not be able to read what we'd send.
+#[derive(Component, Debug)]
+pub(crate) struct AwaitingProtocolCheck(Timer);
+
+/// Replicates a player to everyone, controlled by their client
+fn player_replicate(client_id: ClientId) -> Replicate {
+    Replicate {
+        sync: SyncTarget {
+            prediction: NetworkTarget::All,
+            ..default()
+        },
+        controlled_by: ControlledBy {
+            target: NetworkTarget::Single(client_id),
+            ..default()
+        },
+        // make sure that all entities that are predicted are part of the same replication group
+        group: REPLICATION_GROUP,
+        ..default()
+    }
+}
+
+/// A client whose protocol check failed, or never arrived
+#[derive(Event, Debug)]
+struct ProtocolCheckFailed(ClientId);
+
+/// Starts replicating players whose protocol matches ours, and flags clients built with a
+/// different protocol.
+fn check_client_protocol(
+    mut events: EventReader<MessageEvent<ProtocolCheck>>,
+    fingerprint: Res<ProtocolFingerprint>,
+    awaiting: Query<(Entity, &Player), With<AwaitingProtocolCheck>>,
+    mut failed: EventWriter<ProtocolCheckFailed>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+        let theirs = ProtocolFingerprint(event.message().fingerprint);
+        let Some((entity, _)) = awaiting
+            .iter()
+            .find(|(_, player)| player.client_id == client_id)
+        else {
+            continue;
+        };
+        if theirs == *fingerprint {
+            commands
+                .entity(entity)
+                .remove::<AwaitingProtocolCheck>()
+                .insert(player_replicate(client_id));
+        } else {
+            info!(
+                "Refusing client {client_id:?}: protocol {theirs}, ours is {}",
+                *fingerprint
+            );
+            failed.send(ProtocolCheckFailed(client_id));
+        }
+    }
+}
+
+/// Clients too old to send a protocol check at all fail it once the timeout passes.
+fn expire_protocol_checks(
+    mut awaiting: Query<(&Player, &mut AwaitingProtocolCheck)>,
+    mut failed: EventWriter<ProtocolCheckFailed>,
+    time: Res<Time>,
+) {
+    for (player, mut check) in awaiting.iter_mut() {
+        if check.0.tick(time.delta()).just_finished() {
+            info!(
+                "Refusing client {:?}: no protocol check received",
+                player.client_id
+            );
+            failed.send(ProtocolCheckFailed(player.client_id));
+        }
+    }
+}
+
+/// Tells clients that failed the protocol check to update, then disconnects them.
+fn refuse_failed_protocol_checks(
+    mut failed: EventReader<ProtocolCheckFailed>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    for ProtocolCheckFailed(client_id) in failed.read() {
+        let _ = connection_manager.send_message::<NoticeChannel, _>(
+            *client_id,
+            &mut ServerNotice::ConnectionRejected(RejectReason::OutOfDate),
+        );
+        pending_disconnects.disconnect(*client_id);
+    }
+}
+
+/// Players are only replicated, and so despawned by lightyear when their client goes, once
+/// they pass the protocol check. Until then we despawn them ourselves, when they fail the check
+/// or disconnect, so they don't linger and take up a slot.
+fn despawn_unchecked_players(
+    mut failed: EventReader<ProtocolCheckFailed>,
+    mut disconnects: EventReader<DisconnectEvent>,
+    awaiting: Query<(Entity, &Player), With<AwaitingProtocolCheck>>,
+    mut commands: Commands,
+) {
+    let gone: Vec<ClientId> = failed
+        .read()
+        .map(|ProtocolCheckFailed(client_id)| *client_id)
+        .chain(disconnects.read().map(|event| event.client_id))
+        .collect();
+    for (entity, player) in awaiting.iter() {
+        if gone.contains(&player.client_id) {
+            info!("Despawning unchecked player {:?}", player.client_id);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Clients send a hello once connected, saying which ship they want to fly.
+fn handle_client_hello(
+    mut events: EventReader<MessageEvent<ClientHello>>,
+    mut players: Query<(Entity, &Player, &mut ShipClass, &mut Weapon)>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+        let hello = event.message();
+        info!(
+            "Hello from {client_id:?}, flying a {}",
+            hello.ship_class.name()
+        );
+        let Some((entity, _, mut ship_class, mut weapon)) = players
+            .iter_mut()
+            .find(|(_, player, _, _)| player.client_id == client_id)
+        else {
+            continue;
+        };
+        if *ship_class == hello.ship_class {
+            continue;
+        }
+        *ship_class = hello.ship_class;
+        // keep any weapon from a pickup, otherwise use the new ship's weapon
+        if weapon.expires_tick.is_none() {
+            *weapon = Weapon::new(ship_class.def().weapon);
+        }
+        commands.entity(entity).insert(ship_class.physics_bundle());
     }
 }
 
@@ -271,11 +515,19 @@ const NAMES: [&str; 35] = [
 
 /// Server will manipulate scores when a bullet collides with a player.
 /// the `Score` component is a simple replication. scores fully server-authoritative.
+///
+/// In team modes, hitting the other team also scores for your team. With friendly fire on,
+/// hitting a teammate costs both of you a point. In ball soccer, only goals score.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn handle_hit_event(
     connection_manager: Res<server::ConnectionManager>,
     mut events: EventReader<BulletHitEvent>,
     client_q: Query<&ControlledEntities, Without<Player>>,
-    mut player_q: Query<(&Player, &mut Score)>,
+    mut player_q: Query<(&Player, &mut Score, Has<Shield>, Option<&Team>)>,
+    mut stats: ResMut<ServerStats>,
+    mut team_scores: ResMut<TeamScores>,
+    rules: Res<MatchRules>,
+    mut commands: Commands,
 ) {
     let client_id_to_player_entity = |client_id: ClientId| -> Option<Entity> {
         if let Ok(e) = connection_manager.client_entity(client_id) {
@@ -287,14 +539,39 @@ pub(crate) fn handle_hit_event(
     };
 
     for ev in events.read() {
+        stats.bullet_hits += 1;
         // did they hit a player?
+        if !rules.mode.shots_score() {
+            if ev.victim_client_id.is_some() {
+                stats.bullet_player_hits += 1;
+            }
+            continue;
+        }
         if let Some(victim_entity) = ev.victim_client_id.and_then(client_id_to_player_entity) {
-            if let Ok((_player, mut score)) = player_q.get_mut(victim_entity) {
+            stats.bullet_player_hits += 1;
+            let mut victim_team = None;
+            if let Ok((_player, mut score, shielded, team)) = player_q.get_mut(victim_entity) {
+                if shielded {
+                    // the shield absorbs the hit, and is used up.
+                    commands.entity(victim_entity).remove::<Shield>();
+                    continue;
+                }
                 score.0 -= 1;
+                victim_team = team.copied();
             }
             if let Some(shooter_entity) = client_id_to_player_entity(ev.bullet_owner) {
-                if let Ok((_player, mut score)) = player_q.get_mut(shooter_entity) {
-                    score.0 += 1;
+                if let Ok((_player, mut score, _, shooter_team)) = player_q.get_mut(shooter_entity)
+                {
+                    match (shooter_team.copied(), victim_team) {
+                        (Some(shooter_team), Some(victim_team)) if shooter_team == victim_team => {
+                            score.0 -= 1;
+                        }
+                        (Some(shooter_team), _) => {
+                            score.0 += 1;
+                            team_scores.add(shooter_team, 1);
+                        }
+                        (None, _) => score.0 += 1,
+                    }
                 }
             }
         }
@@ -316,3 +593,79 @@ pub(crate) fn player_movement(
         apply_action_state_to_player_movement(action_state, 0, &mut aiq, tick);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>();
+        app.add_event::<ProtocolCheckFailed>();
+        app.add_event::<DisconnectEvent>();
+        app.add_systems(
+            Update,
+            (expire_protocol_checks, despawn_unchecked_players).chain(),
+        );
+        app
+    }
+
+    fn spawn_player(app: &mut App, client_id: u64, checked: bool) {
+        let mut player = app.world_mut().spawn(Player::new(
+            ClientId::Netcode(client_id),
+            pick_player_name(client_id),
+        ));
+        if !checked {
+            player.insert(AwaitingProtocolCheck(Timer::new(
+                PROTOCOL_CHECK_TIMEOUT,
+                TimerMode::Once,
+            )));
+        }
+    }
+
+    fn players(app: &mut App) -> Vec<ClientId> {
+        app.world_mut()
+            .query::<&Player>()
+            .iter(app.world())
+            .map(|player| player.client_id)
+            .collect()
+    }
+
+    #[test]
+    fn timed_out_player_is_despawned() {
+        let mut app = app();
+        spawn_player(&mut app, 1, false);
+        app.update();
+        assert_eq!(players(&mut app).len(), 1);
+
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(PROTOCOL_CHECK_TIMEOUT);
+        app.update();
+        assert!(players(&mut app).is_empty());
+    }
+
+    #[test]
+    fn rejected_player_is_despawned() {
+        let mut app = app();
+        spawn_player(&mut app, 1, false);
+        spawn_player(&mut app, 2, false);
+        app.world_mut()
+            .send_event(ProtocolCheckFailed(ClientId::Netcode(1)));
+        app.update();
+        assert_eq!(players(&mut app), vec![ClientId::Netcode(2)]);
+    }
+
+    #[test]
+    fn checked_player_is_kept() {
+        let mut app = app();
+        spawn_player(&mut app, 1, true);
+        app.world_mut()
+            .send_event(ProtocolCheckFailed(ClientId::Netcode(1)));
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(PROTOCOL_CHECK_TIMEOUT);
+        app.update();
+        assert_eq!(players(&mut app), vec![ClientId::Netcode(1)]);
+    }
+}
@@ -0,0 +1,583 @@
+//! Arenas are defined in RON files in `assets/arenas/`.
+//!
+//! Native builds read the files from disk when an arena is loaded, so arenas can be added or
+//! edited without rebuilding. The files are also embedded at compile time, as a fallback for
+//! when the directory isn't there, and so wasm clients don't need to fetch them. Client and
+//! server must agree on the arena exactly, so each arena is identified by a hash of its file,
+//! which the server advertises to clients.
+use crate::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Name of the arena used if nothing else is specified
+pub const DEFAULT_ARENA: &str = "default";
+
+/// Directory arena files are read from, override with `ARENA_DIR` env.
+pub const DEFAULT_ARENA_DIR: &str = "assets/arenas";
+
+/// All the arenas compiled in to this build, as (name, RON source).
+pub const BUILTIN_ARENAS: &[(&str, &str)] = &[
+    ("default", include_str!("../../assets/arenas/default.ron")),
+    (
+        "asteroid_field",
+        include_str!("../../assets/arenas/asteroid_field.ron"),
+    ),
+    (
+        "open_space",
+        include_str!("../../assets/arenas/open_space.ron"),
+    ),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArenaDef {
+    pub name: String,
+    /// Polylines, each consecutive pair of points becomes a wall segment
+    pub walls: Vec<Vec<Vec2>>,
+    /// Static, indestructible circular obstacles
+    pub asteroids: Vec<CircleDef>,
+    /// Asteroids that drift, and split up when shot. A new wave spawns once they're all gone.
+    #[serde(default)]
+    pub destructible_asteroids: Vec<CircleDef>,
+    /// Where the physics balls start
+    pub balls: Vec<CircleDef>,
+    /// Connecting players are assigned these in turn
+    pub player_spawns: Vec<Vec2>,
+    /// Where the server spawns pickups and powerups
+    pub pickup_spots: Vec<Vec2>,
+    /// Team goals, for game modes where balls are pushed into goals
+    #[serde(default)]
+    pub goals: Vec<GoalDef>,
+    /// Half the width and height of a wrap-around arena, centred on the origin.
+    /// Anything leaving one edge reappears at the opposite edge, asteroids style.
+    #[serde(default)]
+    pub wrap: Option<Vec2>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CircleDef {
+    pub position: Vec2,
+    pub radius: f32,
+}
+
+/// A circular goal zone, defended by `team`. Balls entering it score for the other team.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GoalDef {
+    pub team: Team,
+    pub position: Vec2,
+    pub radius: f32,
+}
+
+#[derive(Debug)]
+pub enum ArenaError {
+    NotFound(String),
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ArenaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArenaError::NotFound(name) => write!(f, "No arena named '{name}'"),
+            ArenaError::Io(e) => write!(f, "Failed to read arena: {e}"),
+            ArenaError::Parse(e) => write!(f, "Failed to parse arena: {e}"),
+            ArenaError::Invalid(msg) => write!(f, "Invalid arena: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ArenaError {}
+
+/// The arena currently in play, on both server and client.
+#[derive(Resource, Debug, Clone)]
+pub struct Arena {
+    pub def: ArenaDef,
+    /// Hash of the arena file, to check client and server agree
+    pub hash: u64,
+}
+
+impl Arena {
+    /// Loads an arena by name, from `ARENA_DIR` if the file is there, otherwise the builtin copy.
+    pub fn load(name: &str) -> Result<Self, ArenaError> {
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let dir = std::env::var("ARENA_DIR").unwrap_or(DEFAULT_ARENA_DIR.to_string());
+            Self::load_from(std::path::Path::new(&dir), name)
+        }
+        #[cfg(target_family = "wasm")]
+        Self::builtin(name)
+    }
+
+    /// Reads `{name}.ron` from `dir`, falling back to the builtin arena if there's no such file.
+    /// A file that exists but fails to parse is an error, rather than silently ignored.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn load_from(dir: &std::path::Path, name: &str) -> Result<Self, ArenaError> {
+        // clients load whatever name the server sends, so don't let it escape the directory
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(ArenaError::NotFound(name.to_string()));
+        }
+        match std::fs::read_to_string(dir.join(format!("{name}.ron"))) {
+            Ok(source) => Self::from_ron(&source),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::builtin(name),
+            Err(e) => Err(ArenaError::Io(e)),
+        }
+    }
+
+    /// Loads one of the [`BUILTIN_ARENAS`] by name.
+    pub fn builtin(name: &str) -> Result<Self, ArenaError> {
+        let (_, source) = BUILTIN_ARENAS
+            .iter()
+            .find(|(arena_name, _)| *arena_name == name)
+            .ok_or_else(|| ArenaError::NotFound(name.to_string()))?;
+        Self::from_ron(source)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, ArenaError> {
+        let def: ArenaDef = ron::from_str(source).map_err(ArenaError::Parse)?;
+        def.validate()?;
+        Ok(Self {
+            def,
+            hash: arena_hash(source),
+        })
+    }
+
+    /// Spawn point for the nth player to join
+    pub fn player_spawn(&self, n: usize) -> Vec2 {
+        self.def.player_spawns[n % self.def.player_spawns.len()]
+    }
+
+    /// In a wrap-around arena, the position after wrapping, if `p` is out of bounds.
+    pub fn wrap_position(&self, p: Vec2) -> Option<Vec2> {
+        let half = self.def.wrap?;
+        let wrap_axis = |v: f32, half: f32| {
+            if v > half {
+                v - 2.0 * half
+            } else if v < -half {
+                v + 2.0 * half
+            } else {
+                v
+            }
+        };
+        let wrapped = Vec2::new(wrap_axis(p.x, half.x), wrap_axis(p.y, half.y));
+        (wrapped != p).then_some(wrapped)
+    }
+
+    /// Whether something that moved from `from` to `to` in one step must have wrapped around.
+    /// Wrapping moves it by the whole arena size on one axis, nothing else moves half that far.
+    pub fn wrapped_between(&self, from: Vec2, to: Vec2) -> bool {
+        let Some(half) = self.def.wrap else {
+            return false;
+        };
+        let delta = (to - from).abs();
+        delta.x > half.x || delta.y > half.y
+    }
+
+    /// In a wrap-around arena, something within `margin` of an edge is partly visible at the
+    /// opposite edge too. Returns the offsets to draw those copies at.
+    pub fn ghost_offsets(&self, p: Vec2, margin: f32) -> Vec<Vec2> {
+        let Some(half) = self.def.wrap else {
+            return Vec::new();
+        };
+        let axis_offset = |v: f32, half: f32| {
+            if v > half - margin {
+                -2.0 * half
+            } else if v < -half + margin {
+                2.0 * half
+            } else {
+                0.0
+            }
+        };
+        let dx = axis_offset(p.x, half.x);
+        let dy = axis_offset(p.y, half.y);
+        let mut offsets = Vec::new();
+        if dx != 0.0 {
+            offsets.push(Vec2::new(dx, 0.0));
+        }
+        if dy != 0.0 {
+            offsets.push(Vec2::new(0.0, dy));
+        }
+        // near a corner, it shows up in the diagonally opposite corner too
+        if dx != 0.0 && dy != 0.0 {
+            offsets.push(Vec2::new(dx, dy));
+        }
+        offsets
+    }
+}
+
+impl ArenaDef {
+    pub fn validate(&self) -> Result<(), ArenaError> {
+        if self.player_spawns.is_empty() {
+            return Err(ArenaError::Invalid("no player spawns".to_string()));
+        }
+        if self.wrap.is_some_and(|half| half.x <= 0.0 || half.y <= 0.0) {
+            return Err(ArenaError::Invalid(
+                "wrap size must be positive".to_string(),
+            ));
+        }
+        if let Some(i) = self.walls.iter().position(|wall| wall.len() < 2) {
+            return Err(ArenaError::Invalid(format!(
+                "wall {i} needs at least 2 points"
+            )));
+        }
+        if self
+            .asteroids
+            .iter()
+            .chain(self.balls.iter())
+            .chain(self.destructible_asteroids.iter())
+            .any(|circle| circle.radius <= 0.0)
+            || self.goals.iter().any(|goal| goal.radius <= 0.0)
+        {
+            return Err(ArenaError::Invalid("radius must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Identifies an arena file, so client and server can check they have the same one.
+pub fn arena_hash(source: &str) -> u64 {
+    fnv1a(source.as_bytes())
+}
+
+/// Moves anything that has left a wrap-around arena to the opposite edge.
+///
+/// Runs after physics on both client and server, so it's part of the predicted simulation and
+/// replays correctly during rollback.
+pub(crate) fn wrap_positions(arena: Res<Arena>, mut q: Query<(&mut Position, &RigidBody)>) {
+    for (mut position, rigid_body) in q.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        if let Some(wrapped) = arena.wrap_position(position.0) {
+            position.0 = wrapped;
+        }
+    }
+}
+
+/// Marker for entities that make up the arena, so they can be despawned when it changes.
+#[derive(Component)]
+pub struct ArenaGeometry;
+
+/// Static circular obstacle
+#[derive(Component, Debug)]
+pub struct Obstacle {
+    pub radius: f32,
+}
+
+#[derive(Bundle)]
+pub struct ObstacleBundle {
+    position: Position,
+    color: ColorComponent,
+    physics: PhysicsBundle,
+    obstacle: Obstacle,
+    name: Name,
+}
+
+impl ObstacleBundle {
+    pub fn new(position: Vec2, radius: f32, color: Color) -> Self {
+        Self {
+            position: Position(position),
+            color: ColorComponent(color),
+            physics: PhysicsBundle {
+                collider: Collider::circle(radius),
+                collider_density: ColliderDensity(1.0),
+                rigid_body: RigidBody::Static,
+                external_force: ExternalForce::default(),
+            },
+            obstacle: Obstacle { radius },
+            name: Name::new("Obstacle"),
+        }
+    }
+}
+
+/// Sensor zone a team defends. Only spawned in game modes that use goals.
+#[derive(Component, Debug)]
+pub struct GoalZone {
+    pub team: Team,
+    pub radius: f32,
+}
+
+#[derive(Bundle)]
+pub struct GoalZoneBundle {
+    position: Position,
+    goal: GoalZone,
+    collider: Collider,
+    sensor: Sensor,
+    rigid_body: RigidBody,
+    name: Name,
+}
+
+impl GoalZoneBundle {
+    pub fn new(def: &GoalDef) -> Self {
+        Self {
+            position: Position(def.position),
+            goal: GoalZone {
+                team: def.team,
+                radius: def.radius,
+            },
+            collider: Collider::circle(def.radius),
+            sensor: Sensor,
+            rigid_body: RigidBody::Static,
+            name: Name::new("Goal"),
+        }
+    }
+}
+
+/// (Re)builds walls, obstacles and goals whenever the `Arena` or `MatchRules` change.
+///
+/// Runs on both client and server: arena geometry isn't replicated, both sides build it from
+/// the same arena definition.
+pub(crate) fn spawn_arena_geometry(
+    arena: Res<Arena>,
+    rules: Res<MatchRules>,
+    existing: Query<Entity, With<ArenaGeometry>>,
+    mut commands: Commands,
+) {
+    for e in existing.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    info!(
+        "Building arena '{}' (hash {:x})",
+        arena.def.name, arena.hash
+    );
+    for wall in arena.def.walls.iter() {
+        for segment in wall.windows(2) {
+            commands.spawn((
+                WallBundle::new(segment[0], segment[1], Color::WHITE),
+                ArenaGeometry,
+            ));
+        }
+    }
+    for asteroid in arena.def.asteroids.iter() {
+        commands.spawn((
+            ObstacleBundle::new(
+                asteroid.position,
+                asteroid.radius,
+                Color::srgb(0.6, 0.6, 0.6),
+            ),
+            ArenaGeometry,
+        ));
+    }
+    if rules.mode.uses_goals() {
+        if arena.def.goals.is_empty() {
+            warn!(
+                "{} needs goals, but arena '{}' has none",
+                rules.mode.name(),
+                arena.def.name
+            );
+        }
+        for goal in arena.def.goals.iter() {
+            commands.spawn((GoalZoneBundle::new(goal), ArenaGeometry));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = r#"(
+        name: "minimal",
+        walls: [[(-10.0, 0.0), (10.0, 0.0)]],
+        asteroids: [(position: (0.0, 5.0), radius: 2.0)],
+        balls: [],
+        player_spawns: [(0.0, -5.0)],
+        pickup_spots: [],
+    )"#;
+
+    fn minimal_def() -> ArenaDef {
+        Arena::from_ron(MINIMAL).unwrap().def
+    }
+
+    #[test]
+    fn builtin_arenas_load() {
+        for (name, source) in BUILTIN_ARENAS {
+            let arena = Arena::builtin(name).unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert_eq!(arena.def.name, *name);
+            assert_eq!(arena.hash, arena_hash(source));
+        }
+        assert!(Arena::builtin(DEFAULT_ARENA).is_ok());
+    }
+
+    #[test]
+    fn unknown_builtin_is_not_found() {
+        assert!(matches!(
+            Arena::builtin("nowhere"),
+            Err(ArenaError::NotFound(name)) if name == "nowhere"
+        ));
+    }
+
+    /// An empty directory for one test's arena files
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("arenas-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn file_on_disk_is_loaded() {
+        let dir = test_dir("on-disk");
+        std::fs::write(dir.join("minimal.ron"), MINIMAL).unwrap();
+        let arena = Arena::load_from(&dir, "minimal").unwrap();
+        assert_eq!(arena.def.name, "minimal");
+        assert_eq!(arena.hash, arena_hash(MINIMAL));
+        // a file with a builtin's name replaces the embedded copy
+        std::fs::write(dir.join(format!("{DEFAULT_ARENA}.ron")), MINIMAL).unwrap();
+        assert_eq!(
+            Arena::load_from(&dir, DEFAULT_ARENA).unwrap().hash,
+            arena_hash(MINIMAL)
+        );
+    }
+
+    #[test]
+    fn missing_file_falls_back_to_builtin() {
+        let dir = test_dir("fallback");
+        let arena = Arena::load_from(&dir, DEFAULT_ARENA).unwrap();
+        assert_eq!(arena.hash, Arena::builtin(DEFAULT_ARENA).unwrap().hash);
+        let arena = Arena::load_from(&dir.join("not-there"), DEFAULT_ARENA).unwrap();
+        assert_eq!(arena.hash, Arena::builtin(DEFAULT_ARENA).unwrap().hash);
+        assert!(matches!(
+            Arena::load_from(&dir, "nowhere"),
+            Err(ArenaError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn broken_file_is_an_error() {
+        let dir = test_dir("broken");
+        std::fs::write(dir.join(format!("{DEFAULT_ARENA}.ron")), "(name: ").unwrap();
+        assert!(matches!(
+            Arena::load_from(&dir, DEFAULT_ARENA),
+            Err(ArenaError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn names_cant_leave_the_directory() {
+        let dir = test_dir("escape");
+        std::fs::write(dir.join("minimal.ron"), MINIMAL).unwrap();
+        let inner = dir.join("inner");
+        std::fs::create_dir_all(&inner).unwrap();
+        for name in ["../minimal", "", "a/b", "."] {
+            assert!(
+                matches!(Arena::load_from(&inner, name), Err(ArenaError::NotFound(_))),
+                "{name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn optional_fields_default() {
+        let def = minimal_def();
+        assert_eq!(
+            def.walls,
+            vec![vec![Vec2::new(-10.0, 0.0), Vec2::new(10.0, 0.0)]]
+        );
+        assert_eq!(def.player_spawns, vec![Vec2::new(0.0, -5.0)]);
+        assert!(def.destructible_asteroids.is_empty());
+        assert!(def.goals.is_empty());
+        assert_eq!(def.wrap, None);
+    }
+
+    #[test]
+    fn parses_wrap_and_goals() {
+        let source = MINIMAL.replace(
+            "pickup_spots: [],",
+            "pickup_spots: [], wrap: Some((400.0, 300.0)), \
+             goals: [(team: Red, position: (0.0, 10.0), radius: 3.0)],",
+        );
+        let def = Arena::from_ron(&source).unwrap().def;
+        assert_eq!(def.wrap, Some(Vec2::new(400.0, 300.0)));
+        assert_eq!(def.goals.len(), 1);
+        assert_eq!(def.goals[0].team, Team::Red);
+    }
+
+    #[test]
+    fn bad_ron_is_parse_error() {
+        assert!(matches!(
+            Arena::from_ron("(name: \"broken\","),
+            Err(ArenaError::Parse(_))
+        ));
+        // missing required field
+        assert!(matches!(
+            Arena::from_ron(&MINIMAL.replace("balls: [],", "")),
+            Err(ArenaError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn from_ron_validates() {
+        let source = MINIMAL.replace("player_spawns: [(0.0, -5.0)]", "player_spawns: []");
+        assert!(matches!(
+            Arena::from_ron(&source),
+            Err(ArenaError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn wrapped_between_needs_wrap_and_a_big_jump() {
+        let mut arena = Arena::from_ron(MINIMAL).unwrap();
+        let (left, right) = (Vec2::new(-395.0, 0.0), Vec2::new(395.0, 0.0));
+        assert!(!arena.wrapped_between(left, right));
+
+        arena.def.wrap = Some(Vec2::new(400.0, 300.0));
+        assert!(arena.wrapped_between(left, right));
+        assert!(arena.wrapped_between(Vec2::new(0.0, 295.0), Vec2::new(0.0, -295.0)));
+        // fast, but nowhere near wrapping
+        assert!(!arena.wrapped_between(Vec2::ZERO, Vec2::new(150.0, 150.0)));
+    }
+
+    #[test]
+    fn validate_accepts_minimal() {
+        assert!(minimal_def().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_no_spawns() {
+        let mut def = minimal_def();
+        def.player_spawns.clear();
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+    }
+
+    #[test]
+    fn validate_rejects_bad_wrap() {
+        let mut def = minimal_def();
+        def.wrap = Some(Vec2::new(100.0, 0.0));
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+        def.wrap = Some(Vec2::new(-100.0, 100.0));
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+    }
+
+    #[test]
+    fn validate_rejects_short_wall() {
+        let mut def = minimal_def();
+        def.walls.push(vec![Vec2::ZERO]);
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+    }
+
+    #[test]
+    fn validate_rejects_bad_radius() {
+        let mut def = minimal_def();
+        def.asteroids[0].radius = 0.0;
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+
+        let mut def = minimal_def();
+        def.balls.push(CircleDef {
+            position: Vec2::ZERO,
+            radius: -1.0,
+        });
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+
+        let mut def = minimal_def();
+        def.goals.push(GoalDef {
+            team: Team::Blue,
+            position: Vec2::ZERO,
+            radius: 0.0,
+        });
+        assert!(matches!(def.validate(), Err(ArenaError::Invalid(_))));
+    }
+}
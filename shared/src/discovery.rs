@@ -0,0 +1,37 @@
+//! LAN discovery, for builds without bevygap.
+//!
+//! Servers listen on [`DISCOVERY_PORT`] for UDP packets containing [`DISCOVERY_QUERY`], and
+//! reply with a [`ServerAnnouncement`]. Clients broadcast the query to find servers on the local
+//! network, or send it straight to a server's address to preview it. The round trip doubles as
+//! a ping.
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// UDP port servers answer discovery queries on
+pub const DISCOVERY_PORT: u16 = SERVER_PORT + 1;
+/// What clients send to ask "who's there?"
+pub const DISCOVERY_QUERY: &[u8] = b"bevygap-spaceships?";
+
+/// A server's reply to a discovery query
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServerAnnouncement {
+    /// So clients can skip servers they couldn't talk to anyway
+    pub protocol_id: u64,
+    /// The port to connect to for the game itself
+    pub game_port: u16,
+    pub map: String,
+    pub metadata: ServerMetadata,
+}
+
+impl ServerAnnouncement {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        ron::to_string(self)
+            .expect("announcement should serialize")
+            .into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let source = std::str::from_utf8(bytes).ok()?;
+        ron::from_str(source).ok()
+    }
+}
@@ -106,7 +106,7 @@ fn label_changed(
                     text.sections[2].value.clone_from(&label.sub_text);
                 }
                 text.sections[2].style.font_size = label.size * 0.6;
-                text.sections[2].style.color = label.color.with_alpha(0.5);
+                text.sections[2].style.color = label.color.with_alpha(label.color.alpha().min(0.5));
 
                 *transform =
                     Transform::from_translation(Vec3::new(label.offset.x, label.offset.y, label.z));
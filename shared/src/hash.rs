@@ -0,0 +1,7 @@
+/// FNV-1a, because it's trivial and stable across platforms and builds, unlike the std hasher.
+/// For hashes that client and server compare, like arena files and the protocol fingerprint.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
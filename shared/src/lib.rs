@@ -9,8 +9,13 @@
 use lightyear::prelude::*;
 
 // use shared::config::{Mode, SharedConfig};
+mod arena;
+mod discovery;
+mod hash;
+mod private_key;
 mod protocol_plugin;
 mod shared_plugin;
+mod ships;
 
 #[cfg(feature = "gui")]
 mod entity_label;
@@ -23,7 +28,9 @@ mod renderer;
 pub mod prelude {
     pub use bevy::utils::Duration;
 
-    /// Must match on server and client. Bump it whenever you make breaking changes to the protocol.
+    /// Must match on server, client and the matchmaker's `--lightyear-protocol-id`. A client with
+    /// another id can't connect at all, so can't be told why. Protocol changes are caught by the
+    /// [`ProtocolFingerprint`] instead, which lets the server explain.
     pub const PROTOCOL_ID: u64 = 80085;
     pub const SERVER_PORT: u16 = 6420;
     pub const PHYSICS_SCALE: f32 = 100.0;
@@ -36,15 +43,19 @@ pub mod prelude {
     // For non-bevygap (ie, non-connect token) builds, we use a dummy zeroed key on client and server
     pub const DUMMY_PRIVATE_KEY: [u8; PRIVATE_KEY_BYTES] = [0; PRIVATE_KEY_BYTES];
 
+    pub use super::arena::*;
+    pub use super::discovery::*;
+    pub use super::hash::*;
+    pub use super::private_key::*;
     pub use super::protocol_plugin::*;
     pub use super::shared_plugin::*;
+    pub use super::ships::*;
 
     #[cfg(feature = "gui")]
     pub use super::entity_label::*;
     #[cfg(feature = "gui")]
     pub use super::renderer::*;
 
-    pub use super::read_lightyear_private_key_from_env;
     pub use avian2d::prelude::*;
     pub use leafwing_input_manager::prelude::ActionState;
     pub use lightyear::connection::netcode::PRIVATE_KEY_BYTES;
@@ -64,31 +75,3 @@ pub fn shared_config() -> SharedConfig {
         mode: Mode::Separate,
     }
 }
-
-/// Reads and parses the LIGHTYEAR_PRIVATE_KEY environment variable into a private key.
-pub fn read_lightyear_private_key_from_env() -> Option<[u8; PRIVATE_KEY_BYTES]> {
-    let Ok(key_str) = std::env::var("LIGHTYEAR_PRIVATE_KEY") else {
-        return None;
-    };
-    let private_key: Vec<u8> = key_str
-        .chars()
-        .filter(|c| c.is_ascii_digit() || *c == ',')
-        .collect::<String>()
-        .split(',')
-        .map(|s| {
-            s.parse::<u8>()
-                .expect("Failed to parse number in private key")
-        })
-        .collect();
-
-    if private_key.len() != PRIVATE_KEY_BYTES {
-        panic!(
-            "Private key must contain exactly {} numbers",
-            PRIVATE_KEY_BYTES
-        );
-    }
-
-    let mut bytes = [0u8; PRIVATE_KEY_BYTES];
-    bytes.copy_from_slice(&private_key);
-    Some(bytes)
-}
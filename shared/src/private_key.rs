@@ -0,0 +1,228 @@
+//! Loading the lightyear private key that connect tokens are signed with.
+//!
+//! The server and the matchmaker must use the same key. It can be given as a list of decimal
+//! numbers (as printed by rust, eg: `[1, 2, 3, ...]`), 64 hex digits, or base64, either directly
+//! in `LIGHTYEAR_PRIVATE_KEY` or in a file named by `LIGHTYEAR_PRIVATE_KEY_FILE`.
+use crate::prelude::*;
+use base64::prelude::*;
+use std::path::PathBuf;
+
+pub const PRIVATE_KEY_ENV: &str = "LIGHTYEAR_PRIVATE_KEY";
+pub const PRIVATE_KEY_FILE_ENV: &str = "LIGHTYEAR_PRIVATE_KEY_FILE";
+
+#[derive(Debug)]
+pub enum PrivateKeyError {
+    /// Neither env var is set, where a real key is required
+    Missing,
+    ReadFile(PathBuf, std::io::Error),
+    /// Not in any of the formats we accept
+    Format(String),
+    /// Parsed, but not [`PRIVATE_KEY_BYTES`] long
+    WrongLength(usize),
+    /// The zeroed [`DUMMY_PRIVATE_KEY`], where a real key is required
+    DummyKey,
+}
+
+impl std::fmt::Display for PrivateKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivateKeyError::Missing => {
+                write!(f, "Set {PRIVATE_KEY_ENV} or {PRIVATE_KEY_FILE_ENV}")
+            }
+            PrivateKeyError::ReadFile(path, e) => {
+                write!(f, "Failed to read private key file {}: {e}", path.display())
+            }
+            PrivateKeyError::Format(msg) => write!(f, "Invalid private key: {msg}"),
+            PrivateKeyError::WrongLength(len) => write!(
+                f,
+                "Private key must be {PRIVATE_KEY_BYTES} bytes, got {len}"
+            ),
+            PrivateKeyError::DummyKey => {
+                write!(f, "Refusing to use the zeroed dummy private key")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrivateKeyError {}
+
+/// Parses a key given as a decimal list, hex or base64.
+pub fn parse_private_key(input: &str) -> Result<[u8; PRIVATE_KEY_BYTES], PrivateKeyError> {
+    let input = input.trim();
+    let list = input
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(input);
+    let bytes = if list.contains(|c: char| c == ',' || c.is_whitespace()) {
+        parse_decimal_list(list)?
+    } else if let Some(bytes) = parse_hex(input.strip_prefix("0x").unwrap_or(input)) {
+        bytes
+    } else {
+        BASE64_STANDARD.decode(input).map_err(|_| {
+            PrivateKeyError::Format("expected a decimal list, 64 hex digits or base64".to_string())
+        })?
+    };
+    bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| PrivateKeyError::WrongLength(bytes.len()))
+}
+
+fn parse_decimal_list(list: &str) -> Result<Vec<u8>, PrivateKeyError> {
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<u8>()
+                .map_err(|_| PrivateKeyError::Format(format!("'{s}' isn't a number from 0 to 255")))
+        })
+        .collect()
+}
+
+/// None unless it's all hex digit pairs, so base64 gets a chance
+fn parse_hex(input: &str) -> Option<Vec<u8>> {
+    if input.is_empty() || input.len() % 2 != 0 || !input.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&input[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Reads the key from `LIGHTYEAR_PRIVATE_KEY`, or else the file named by
+/// `LIGHTYEAR_PRIVATE_KEY_FILE`. `Ok(None)` if neither is set.
+pub fn read_lightyear_private_key_from_env(
+) -> Result<Option<[u8; PRIVATE_KEY_BYTES]>, PrivateKeyError> {
+    if let Ok(key) = std::env::var(PRIVATE_KEY_ENV) {
+        return parse_private_key(&key).map(Some);
+    }
+    let Ok(path) = std::env::var(PRIVATE_KEY_FILE_ENV) else {
+        return Ok(None);
+    };
+    let path = PathBuf::from(path);
+    let key = std::fs::read_to_string(&path).map_err(|e| PrivateKeyError::ReadFile(path, e))?;
+    parse_private_key(&key).map(Some)
+}
+
+/// For when connect tokens are in use, and anyone could sign their own with the dummy key.
+pub fn reject_dummy_key(
+    key: [u8; PRIVATE_KEY_BYTES],
+) -> Result<[u8; PRIVATE_KEY_BYTES], PrivateKeyError> {
+    if key == DUMMY_PRIVATE_KEY {
+        Err(PrivateKeyError::DummyKey)
+    } else {
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The bytes 1 to 32, which each test spells in a different format
+    fn expected() -> [u8; PRIVATE_KEY_BYTES] {
+        std::array::from_fn(|i| i as u8 + 1)
+    }
+
+    fn decimal_list() -> String {
+        expected()
+            .iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    const HEX: &str = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20";
+    const BASE64: &str = "AQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyA=";
+
+    #[test]
+    fn decimal_list_with_brackets() {
+        let key = parse_private_key(&format!("[{}]", decimal_list())).unwrap();
+        assert_eq!(key, expected());
+    }
+
+    #[test]
+    fn decimal_list_without_brackets_or_spaces() {
+        let key = parse_private_key(&decimal_list().replace(' ', "")).unwrap();
+        assert_eq!(key, expected());
+    }
+
+    #[test]
+    fn decimal_list_separated_by_spaces() {
+        let key = parse_private_key(&decimal_list().replace(',', "")).unwrap();
+        assert_eq!(key, expected());
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_private_key(HEX).unwrap(), expected());
+        assert_eq!(parse_private_key(&HEX.to_uppercase()).unwrap(), expected());
+    }
+
+    #[test]
+    fn hex_with_prefix_and_whitespace() {
+        let key = parse_private_key(&format!("  0x{HEX}\n")).unwrap();
+        assert_eq!(key, expected());
+    }
+
+    #[test]
+    fn padded_base64() {
+        assert_eq!(BASE64.len(), 44);
+        assert_eq!(parse_private_key(BASE64).unwrap(), expected());
+    }
+
+    #[test]
+    fn odd_length_hex_is_rejected() {
+        let result = parse_private_key(&HEX[1..]);
+        assert!(
+            matches!(result, Err(PrivateKeyError::Format(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn short_hex_is_wrong_length() {
+        let result = parse_private_key("0102");
+        assert!(
+            matches!(result, Err(PrivateKeyError::WrongLength(2))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn out_of_range_decimal_is_rejected() {
+        let list = decimal_list().replacen("1,", "256,", 1);
+        let result = parse_private_key(&list);
+        assert!(
+            matches!(result, Err(PrivateKeyError::Format(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn short_decimal_list_is_wrong_length() {
+        let result = parse_private_key("[1, 2, 3]");
+        assert!(
+            matches!(result, Err(PrivateKeyError::WrongLength(3))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let result = parse_private_key("not a key!");
+        assert!(
+            matches!(result, Err(PrivateKeyError::Format(_))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn dummy_key_is_rejected() {
+        assert!(matches!(
+            reject_dummy_key(DUMMY_PRIVATE_KEY),
+            Err(PrivateKeyError::DummyKey)
+        ));
+        assert_eq!(reject_dummy_key(expected()).unwrap(), expected());
+    }
+}
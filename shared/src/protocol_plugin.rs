@@ -1,5 +1,8 @@
+use crate::hash::fnv1a;
+use crate::ships::ShipClass;
 use crate::FIXED_TIMESTEP_HZ;
 use avian2d::prelude::*;
+use bevy::color::palettes::css;
 use bevy::prelude::*;
 use bevy::utils::Duration;
 use leafwing_input_manager::prelude::*;
@@ -106,25 +109,6 @@ impl PhysicsBundle {
             external_force: ExternalForce::default(),
         }
     }
-
-    pub fn player_ship() -> Self {
-        // triangle ship, pointing up the screen
-        let points = vec![
-            Vec2::new(0.0, SHIP_LENGTH / 2.),
-            Vec2::new(SHIP_WIDTH / 2., -SHIP_LENGTH / 2.),
-            Vec2::new(-SHIP_WIDTH / 2., -SHIP_LENGTH / 2.),
-        ];
-        let collider = Collider::convex_hull(points).unwrap();
-        // Note: due to a bug in older (?) versions of bevy_xpbd, using a triangle collider here
-        // sometimes caused strange behaviour. Unsure if this is fixed now.
-        // Also, counter-clockwise ordering of points was required for convex hull creation (?)
-        Self {
-            collider,
-            collider_density: ColliderDensity(1.0),
-            rigid_body: RigidBody::Dynamic,
-            external_force: ExternalForce::ZERO.with_persistence(false),
-        }
-    }
 }
 
 // Components
@@ -156,6 +140,8 @@ pub struct BulletHitEvent {
     pub bullet_color: Color,
     /// if it struck a player, this is their clientid:
     pub victim_client_id: Option<ClientId>,
+    /// whatever the bullet hit
+    pub target: Entity,
     pub position: Vec2,
 }
 
@@ -182,6 +168,89 @@ impl BallMarker {
     }
 }
 
+/// Smallest asteroid fragment. Anything that would split smaller is just destroyed.
+pub const MIN_ASTEROID_RADIUS: f32 = 10.0;
+
+/// Server-authoritative asteroids, which break into smaller fragments when shot enough.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Asteroid {
+    pub radius: f32,
+    /// Bullet hits left before it breaks
+    pub health: u8,
+}
+
+impl Asteroid {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            // bigger asteroids take more hits
+            health: (radius / MIN_ASTEROID_RADIUS).ceil() as u8,
+        }
+    }
+
+    /// The radius of the two fragments this splits into, if they're big enough to exist
+    pub fn fragment_radius(&self) -> Option<f32> {
+        let radius = self.radius * 0.6;
+        (radius >= MIN_ASTEROID_RADIUS).then_some(radius)
+    }
+
+    pub fn physics_bundle(&self) -> PhysicsBundle {
+        // a lumpy polygon rather than a circle. It must be the same on client and server,
+        // so the lumps are a fixed pattern rather than random.
+        const LUMPS: [f32; 9] = [1.0, 0.85, 0.95, 0.8, 1.0, 0.9, 0.75, 0.95, 0.85];
+        let points = LUMPS
+            .iter()
+            .enumerate()
+            .map(|(i, lump)| {
+                Vec2::from_angle(i as f32 * std::f32::consts::TAU / LUMPS.len() as f32)
+                    * self.radius
+                    * lump
+            })
+            .collect();
+        PhysicsBundle {
+            collider: Collider::convex_hull(points).unwrap(),
+            collider_density: ColliderDensity(2.0),
+            rigid_body: RigidBody::Dynamic,
+            external_force: ExternalForce::ZERO.with_persistence(false),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct AsteroidBundle {
+    position: Position,
+    velocity: LinearVelocity,
+    color: ColorComponent,
+    replicate: Replicate,
+    asteroid: Asteroid,
+    physics: PhysicsBundle,
+    name: Name,
+}
+
+impl AsteroidBundle {
+    pub fn new(radius: f32, position: Vec2, velocity: Vec2) -> Self {
+        let asteroid = Asteroid::new(radius);
+        // predicted like balls, so clients can predict ships bouncing off them
+        let replicate = Replicate {
+            sync: SyncTarget {
+                prediction: NetworkTarget::All,
+                ..default()
+            },
+            group: REPLICATION_GROUP,
+            ..default()
+        };
+        Self {
+            position: Position(position),
+            velocity: LinearVelocity(velocity),
+            color: ColorComponent(css::SANDY_BROWN.into()),
+            replicate,
+            physics: asteroid.physics_bundle(),
+            asteroid,
+            name: Name::new("Asteroid"),
+        }
+    }
+}
+
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct BulletMarker {
     pub owner: ClientId,
@@ -193,20 +262,241 @@ impl BulletMarker {
     }
 }
 
-// Limiting firing rate: once you fire on `last_fire_tick` you have to wait `cooldown` ticks before firing again.
+/// The different guns a ship can have. Ships start with their class's weapon, pickups grant
+/// the others for a limited time.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum WeaponKind {
+    #[default]
+    Standard,
+    /// Fires a fan of bullets
+    SpreadShot,
+    /// Short cooldown, slightly slower bullets
+    RapidFire,
+    /// Hold fire to charge, release to fire a very fast bullet
+    ChargedRail,
+    /// Slow bullets that steer towards the nearest enemy
+    HomingMissile,
+}
+
+/// Number of ticks fire must be held to charge the rail gun
+pub const RAIL_CHARGE_TICKS: u16 = (FIXED_TIMESTEP_HZ / 2.0) as u16;
+
+impl WeaponKind {
+    /// Ticks to wait after firing before we can fire again
+    pub fn cooldown(&self) -> u16 {
+        match self {
+            WeaponKind::Standard => (FIXED_TIMESTEP_HZ / 5.0) as u16,
+            WeaponKind::SpreadShot => (FIXED_TIMESTEP_HZ / 3.0) as u16,
+            WeaponKind::RapidFire => (FIXED_TIMESTEP_HZ / 16.0) as u16,
+            WeaponKind::ChargedRail => (FIXED_TIMESTEP_HZ / 4.0) as u16,
+            WeaponKind::HomingMissile => (FIXED_TIMESTEP_HZ / 2.0) as u16,
+        }
+    }
+
+    pub fn bullet_speed(&self) -> f32 {
+        match self {
+            WeaponKind::Standard | WeaponKind::SpreadShot => 500.0,
+            WeaponKind::RapidFire => 420.0,
+            WeaponKind::ChargedRail => 1200.0,
+            WeaponKind::HomingMissile => 300.0,
+        }
+    }
+
+    /// Angles (radians, relative to the ship's heading) of the bullets fired with each shot.
+    pub fn projectile_angles(&self) -> &'static [f32] {
+        match self {
+            WeaponKind::SpreadShot => &[-0.25, 0.0, 0.25],
+            _ => &[0.0],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeaponKind::Standard => "Blaster",
+            WeaponKind::SpreadShot => "Spread",
+            WeaponKind::RapidFire => "Rapid",
+            WeaponKind::ChargedRail => "Rail",
+            WeaponKind::HomingMissile => "Homing",
+        }
+    }
+}
+
+// Limiting firing rate: once you fire on `last_fire_tick` you have to wait `kind.cooldown()` ticks before firing again.
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Weapon {
+    pub kind: WeaponKind,
     pub last_fire_tick: Tick,
-    pub cooldown: u16,
-    pub bullet_speed: f32,
+    /// Temporary weapons revert to the ship class's weapon on this tick
+    pub expires_tick: Option<Tick>,
+    /// Number of ticks fire has been held for, for weapons that charge up
+    pub charge: u16,
 }
 
 impl Weapon {
-    pub fn new(cooldown: u16) -> Self {
+    pub fn new(kind: WeaponKind) -> Self {
         Self {
+            kind,
             last_fire_tick: Tick(0),
-            bullet_speed: 500.0,
-            cooldown,
+            expires_tick: None,
+            charge: 0,
+        }
+    }
+
+    /// A weapon that reverts to the ship's own weapon after `duration_ticks`
+    pub fn temporary(kind: WeaponKind, current_tick: Tick, duration_ticks: u16) -> Self {
+        Self {
+            expires_tick: Some(Tick(current_tick.0.wrapping_add(duration_ticks))),
+            ..Self::new(kind)
+        }
+    }
+
+    pub fn is_expired(&self, current_tick: Tick) -> bool {
+        self.expires_tick
+            .is_some_and(|expires_tick| current_tick - expires_tick >= 0)
+    }
+}
+
+/// Bullets with this component steer towards the nearest enemy ship
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Homing {
+    /// Max radians per tick the bullet can turn
+    pub turn_rate: f32,
+}
+
+/// Timed effects granted by powerup pickups
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PowerupKind {
+    /// Absorbs one hit
+    Shield,
+    /// More thrust
+    SpeedBoost,
+    /// Nearly invisible to other players
+    Cloak,
+}
+
+impl PowerupKind {
+    /// How long the effect lasts
+    pub fn duration_ticks(&self) -> u16 {
+        let secs = match self {
+            PowerupKind::Shield => 20,
+            PowerupKind::SpeedBoost => 8,
+            PowerupKind::Cloak => 10,
+        };
+        FIXED_TIMESTEP_HZ as u16 * secs
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerupKind::Shield => "Shield",
+            PowerupKind::SpeedBoost => "Speed",
+            PowerupKind::Cloak => "Cloak",
+        }
+    }
+}
+
+/// Powerup effects are components on the player entity, removed by the server when they expire.
+pub trait TimedEffect: Component {
+    fn new(expires_tick: Tick) -> Self;
+    fn expires_tick(&self) -> Tick;
+
+    fn is_expired(&self, current_tick: Tick) -> bool {
+        current_tick - self.expires_tick() >= 0
+    }
+}
+
+/// Absorbs the next hit, instead of losing a point
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Shield {
+    pub expires_tick: Tick,
+}
+
+/// Multiplies thrust
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SpeedBoost {
+    pub expires_tick: Tick,
+}
+
+pub const SPEED_BOOST_MULTIPLIER: f32 = 1.6;
+
+/// Renders the ship (nearly) invisible to other players
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cloak {
+    pub expires_tick: Tick,
+}
+
+impl TimedEffect for Shield {
+    fn new(expires_tick: Tick) -> Self {
+        Self { expires_tick }
+    }
+    fn expires_tick(&self) -> Tick {
+        self.expires_tick
+    }
+}
+
+impl TimedEffect for SpeedBoost {
+    fn new(expires_tick: Tick) -> Self {
+        Self { expires_tick }
+    }
+    fn expires_tick(&self) -> Tick {
+        self.expires_tick
+    }
+}
+
+impl TimedEffect for Cloak {
+    fn new(expires_tick: Tick) -> Self {
+        Self { expires_tick }
+    }
+    fn expires_tick(&self) -> Tick {
+        self.expires_tick
+    }
+}
+
+pub const PICKUP_RADIUS: f32 = 12.0;
+
+/// What you get when you fly into a pickup
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PickupKind {
+    Weapon(WeaponKind),
+    Powerup(PowerupKind),
+}
+
+impl PickupKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PickupKind::Weapon(kind) => kind.name(),
+            PickupKind::Powerup(kind) => kind.name(),
+        }
+    }
+}
+
+/// Server-spawned collectables. Only the server simulates the collisions, so clients don't
+/// get colliders for these, they just render them.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Pickup {
+    pub kind: PickupKind,
+}
+
+#[derive(Bundle)]
+pub struct PickupBundle {
+    position: Position,
+    pickup: Pickup,
+    collider: Collider,
+    sensor: Sensor,
+    rigid_body: RigidBody,
+    replicate: Replicate,
+    name: Name,
+}
+
+impl PickupBundle {
+    pub fn new(kind: PickupKind, position: Vec2) -> Self {
+        Self {
+            position: Position(position),
+            pickup: Pickup { kind },
+            collider: Collider::circle(PICKUP_RADIUS),
+            sensor: Sensor,
+            rigid_body: RigidBody::Static,
+            replicate: Replicate::default(),
+            name: Name::new("Pickup"),
         }
     }
 }
@@ -215,6 +505,153 @@ impl Weapon {
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Score(pub i32);
 
+/// A player's stats over every session they've played on this server, kept by the server's
+/// stats store and shown on the scoreboard.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct LifetimeStats {
+    /// Times they shot another player
+    pub kills: u32,
+    /// Times they were shot by another player
+    pub deaths: u32,
+    /// Bullets that hit anything other than a wall
+    pub hits: u32,
+    pub shots: u32,
+    pub playtime_secs: u64,
+}
+
+impl LifetimeStats {
+    /// Fraction of shots that hit something, from 0.0 to 1.0
+    pub fn accuracy(&self) -> f32 {
+        if self.shots == 0 {
+            0.0
+        } else {
+            self.hits as f32 / self.shots as f32
+        }
+    }
+
+    pub fn add(&mut self, other: &LifetimeStats) {
+        self.kills += other.kills;
+        self.deaths += other.deaths;
+        self.hits += other.hits;
+        self.shots += other.shots;
+        self.playtime_secs += other.playtime_secs;
+    }
+}
+
+/// Which side a player is on, in team game modes. Not present in free-for-all.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::Red, Team::Blue];
+
+    pub fn color(&self) -> Color {
+        match self {
+            Team::Red => css::TOMATO.into(),
+            Team::Blue => css::DODGER_BLUE.into(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Team::Red => "Red",
+            Team::Blue => "Blue",
+        }
+    }
+
+    pub fn opponent(&self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// Everyone for themselves, shooting other players scores points
+    #[default]
+    FreeForAll,
+    /// Two teams, shooting the other team scores points for your team
+    TeamDeathmatch,
+    /// Two teams, pushing a ball into the other team's goal scores big, shooting still counts
+    CaptureTheBall,
+    /// Two teams and one ball. Only goals score, bullets are just for pushing the ball around.
+    BallSoccer,
+}
+
+impl GameMode {
+    pub fn has_teams(&self) -> bool {
+        !matches!(self, GameMode::FreeForAll)
+    }
+
+    /// Whether the arena's goals are in play
+    pub fn uses_goals(&self) -> bool {
+        matches!(self, GameMode::CaptureTheBall | GameMode::BallSoccer)
+    }
+
+    /// Whether hitting players with bullets changes scores
+    pub fn shots_score(&self) -> bool {
+        !matches!(self, GameMode::BallSoccer)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::FreeForAll => "Free For All",
+            GameMode::TeamDeathmatch => "Team Deathmatch",
+            GameMode::CaptureTheBall => "Capture The Ball",
+            GameMode::BallSoccer => "Ball Soccer",
+        }
+    }
+}
+
+/// How the server is running the game. Replicated so clients can predict the same rules.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Resource)]
+pub struct MatchRules {
+    pub mode: GameMode,
+    /// Whether bullets hit teammates. Without friendly fire, they pass through.
+    pub friendly_fire: bool,
+}
+
+impl MatchRules {
+    /// Whether a bullet from a player on `shooter` team can hit a player on `victim` team
+    pub fn can_hit(&self, shooter: Option<&Team>, victim: Option<&Team>) -> bool {
+        if !self.mode.has_teams() || self.friendly_fire {
+            return true;
+        }
+        match (shooter, victim) {
+            (Some(a), Some(b)) => a != b,
+            _ => true,
+        }
+    }
+}
+
+/// Team totals in team game modes, server-authoritative and replicated.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Resource)]
+pub struct TeamScores {
+    pub red: i32,
+    pub blue: i32,
+}
+
+impl TeamScores {
+    pub fn get(&self, team: Team) -> i32 {
+        match team {
+            Team::Red => self.red,
+            Team::Blue => self.blue,
+        }
+    }
+
+    pub fn add(&mut self, team: Team, points: i32) {
+        match team {
+            Team::Red => self.red += points,
+            Team::Blue => self.blue += points,
+        }
+    }
+}
+
 // despawns `lifetime` ticks after `origin_tick`
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Lifetime {
@@ -230,6 +667,27 @@ pub enum PlayerActions {
     Left,
     Right,
     Fire,
+    /// Direction to point the ship in, from a gamepad stick or the mouse.
+    /// Ignored while turning with Left/Right, or when near zero.
+    #[actionlike(DualAxis)]
+    Aim,
+    /// Analog thrust, 0.0 to 1.0. Holding Up is full thrust.
+    #[actionlike(Axis)]
+    Thrust,
+}
+
+/// Aim inputs shorter than this are ignored, so a resting stick doesn't steer the ship.
+pub const AIM_DEADZONE: f32 = 0.2;
+
+impl PlayerActions {
+    /// How much thrust is requested, from 0.0 to 1.0
+    pub fn throttle(action: &ActionState<PlayerActions>) -> f32 {
+        if action.pressed(&PlayerActions::Up) {
+            1.0
+        } else {
+            action.value(&PlayerActions::Thrust).clamp(0.0, 1.0)
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Resource)]
@@ -237,57 +695,369 @@ pub struct ServerMetadata {
     pub location: String,
     pub fqdn: String,
     pub build_info: String,
+    /// Number of players currently connected
+    pub players: u32,
+    /// Player limit, connections beyond this are rejected
+    pub max_players: u32,
+}
+
+/// The arena currently in play. The server changes this when rotating maps, and clients rebuild
+/// their arena to match.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Resource)]
+pub struct CurrentMap {
+    /// Name of the arena file, without the `.ron`
+    pub name: String,
+    /// Hash of the arena file, clients check theirs matches
+    pub hash: u64,
 }
 
 /// Just used to replicate resources, like ServerMetadata
 #[derive(Channel)]
 pub struct ResourceChannel;
 
+/// Announcements from the server, rendered as a banner by clients.
+///
+/// Out of date clients must still be able to read `ConnectionRejected`, so only add variants at
+/// the end.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ServerNotice {
+    /// The server is going away, and will disconnect everyone in `seconds`.
+    ShuttingDown { seconds: u32 },
+    /// You haven't touched the controls for a while, and will be kicked in `seconds`.
+    IdleWarning { seconds: u32 },
+    /// You were disconnected for being idle.
+    IdleKicked,
+    /// Clears any notice currently being shown.
+    Clear,
+    /// Sent just before the server disconnects a client it won't accept.
+    ConnectionRejected(RejectReason),
+    /// The map rotation will switch to arena `name` in `seconds`.
+    MapChanging { name: String, seconds: u32 },
+}
+
+/// Sent to all clients when a ball goes in a goal, so they can show an effect.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GoalScored {
+    /// The team that scored, ie not the team defending the goal
+    pub team: Team,
+    /// Last player to touch the ball, if they were on the scoring team
+    pub scorer: Option<ClientId>,
+    /// Where the goal is
+    pub position: Vec2,
+}
+
+/// Game events the clients want to show effects for, like goals.
+#[derive(Channel)]
+pub struct GameEventChannel;
+
+/// The first thing a client sends once connected, describing how it wants to play.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClientHello {
+    pub ship_class: ShipClass,
+    /// Random token the client keeps between sessions, so the server can recognise a player
+    /// who reconnects. Not a password, but don't log it.
+    pub identity: String,
+}
+
+/// Longest identity token the server accepts
+pub const MAX_IDENTITY_LEN: usize = 64;
+
+/// Identity tokens are short strings of ascii letters, digits, `-` and `_`.
+pub fn is_valid_identity(identity: &str) -> bool {
+    !identity.is_empty()
+        && identity.len() <= MAX_IDENTITY_LEN
+        && identity
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Reliable messages from clients to the server
+#[derive(Channel)]
+pub struct ClientChannel;
+
+/// Everything the [`ProtocolPlugin`] registers, in registration order, as the type's name and a
+/// version tag.
+///
+/// Lightyear assigns ids in registration order, so the order is part of the protocol. Bump a
+/// type's tag whenever its serialized form or how it's registered changes. Only the tags go in
+/// the [`ProtocolFingerprint`], so renaming a type means updating its name here, but doesn't
+/// change the fingerprint. Registering something that doesn't match this list panics at startup.
+const PROTOCOL: &[(&str, &str)] = &[
+    // FROZEN, see `ProtocolPlugin::build`
+    ("ProtocolCheckChannel", "protocol_check_channel.v1"),
+    ("ProtocolCheck", "protocol_check.v1"),
+    ("NoticeChannel", "notice_channel.v1"),
+    ("ServerNotice", "server_notice.v1"),
+    // resources
+    ("ServerMetadata", "server_metadata.v1"),
+    ("CurrentMap", "current_map.v1"),
+    ("MatchRules", "match_rules.v1"),
+    ("TeamScores", "team_scores.v1"),
+    ("ResourceChannel", "resource_channel.v1"),
+    // messages
+    ("ClientChannel", "client_channel.v1"),
+    ("ClientHello", "client_hello.v1"),
+    ("GameEventChannel", "game_event_channel.v1"),
+    ("GoalScored", "goal_scored.v1"),
+    ("PlayerActions", "player_actions.v1"),
+    // components
+    ("Player", "player.v1"),
+    ("ColorComponent", "color.v1"),
+    ("Name", "name.v1"),
+    ("BallMarker", "ball.v1"),
+    ("Asteroid", "asteroid.v1"),
+    ("BulletMarker", "bullet.v1"),
+    ("Lifetime", "lifetime.v1"),
+    ("Homing", "homing.v1"),
+    ("Pickup", "pickup.v1"),
+    ("Shield", "shield.v1"),
+    ("SpeedBoost", "speed_boost.v1"),
+    ("Cloak", "cloak.v1"),
+    ("Score", "score.v1"),
+    ("Team", "team.v1"),
+    ("ShipClass", "ship_class.v1"),
+    ("LifetimeStats", "lifetime_stats.v1"),
+    ("LinearVelocity", "linear_velocity.v1"),
+    ("AngularVelocity", "angular_velocity.v1"),
+    ("Weapon", "weapon.v1"),
+    ("Position", "position.v1"),
+    ("Rotation", "rotation.v1"),
+];
+
+/// Hash of the [`PROTOCOL`] version tags, in order. Client and server can only understand each
+/// other if theirs match.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolFingerprint(pub u64);
+
+impl ProtocolFingerprint {
+    fn of(protocol: &[(&str, &str)]) -> Self {
+        let tags: Vec<&str> = protocol.iter().map(|(_, tag)| *tag).collect();
+        Self(fnv1a(tags.join("\n").as_bytes()))
+    }
+}
+
+impl std::fmt::Display for ProtocolFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Checks each registration against [`PROTOCOL`], so the list can't drift from what's actually
+/// registered.
+#[derive(Default)]
+struct FingerprintBuilder {
+    registered: usize,
+}
+
+impl FingerprintBuilder {
+    fn add<T>(&mut self) {
+        let name = std::any::type_name::<T>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        assert_eq!(
+            PROTOCOL.get(self.registered).map(|(name, _)| *name),
+            Some(name),
+            "Registration {} doesn't match PROTOCOL, update it to match the ProtocolPlugin",
+            self.registered
+        );
+        self.registered += 1;
+    }
+
+    fn finish(self) -> ProtocolFingerprint {
+        assert_eq!(
+            self.registered,
+            PROTOCOL.len(),
+            "PROTOCOL lists more than the ProtocolPlugin registers"
+        );
+        ProtocolFingerprint::of(PROTOCOL)
+    }
+}
+
+/// Sent by clients as soon as they connect, so the server can turn away clients built with a
+/// different protocol. Keep this stable, old clients need to send it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProtocolCheck {
+    pub fingerprint: u64,
+}
+
+/// Carries only the [`ProtocolCheck`], so its id never moves
+#[derive(Channel)]
+pub struct ProtocolCheckChannel;
+
+/// Why the server refused to let a client play. Only add variants at the end, see [`ServerNotice`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RejectReason {
+    ServerFull {
+        max_players: u32,
+    },
+    ShuttingDown,
+    /// The client was built with a different protocol
+    OutOfDate,
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::ServerFull { max_players } => {
+                write!(f, "Server is full ({max_players} players)")
+            }
+            RejectReason::ShuttingDown => write!(f, "Server is shutting down"),
+            RejectReason::OutOfDate => write!(f, "Client out of date, please refresh"),
+        }
+    }
+}
+
+/// Reliable channel for [`ServerNotice`] messages
+#[derive(Channel)]
+pub struct NoticeChannel;
+
 // Protocol
 pub struct ProtocolPlugin;
 
 impl Plugin for ProtocolPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<ServerMetadata>();
-        app.register_resource::<ServerMetadata>(ChannelDirection::ServerToClient);
-        app.add_channel::<ResourceChannel>(ChannelSettings {
-            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
-            ..default()
-        });
+        // Everything registered here must be listed in PROTOCOL, which the fingerprint is made
+        // from, so clients from another build are told they're out of date, rather than failing
+        // in confusing ways.
+        let mut fingerprint = FingerprintBuilder::default();
+        macro_rules! channel {
+            ($channel:ty, $settings:expr) => {{
+                fingerprint.add::<$channel>();
+                app.add_channel::<$channel>($settings)
+            }};
+        }
+        macro_rules! message {
+            ($message:ty, $direction:expr) => {{
+                fingerprint.add::<$message>();
+                app.register_message::<$message>($direction)
+            }};
+        }
+        macro_rules! resource {
+            ($resource:ty, $direction:expr) => {{
+                fingerprint.add::<$resource>();
+                app.init_resource::<$resource>();
+                app.register_resource::<$resource>($direction)
+            }};
+        }
+        macro_rules! component {
+            ($component:ty, $direction:expr) => {{
+                fingerprint.add::<$component>();
+                app.register_component::<$component>($direction)
+            }};
+        }
 
+        // FROZEN: the protocol check and the reply rejecting an out of date client are
+        // registered first, so their ids are the same in every build. Don't add anything above
+        // them, and only ever add variants to the end of `ServerNotice` and `RejectReason`.
+        channel!(
+            ProtocolCheckChannel,
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            }
+        );
+        message!(ProtocolCheck, ChannelDirection::ClientToServer);
+        channel!(
+            NoticeChannel,
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            }
+        );
+        message!(ServerNotice, ChannelDirection::ServerToClient);
+
+        resource!(ServerMetadata, ChannelDirection::ServerToClient);
+        resource!(CurrentMap, ChannelDirection::ServerToClient);
+        resource!(MatchRules, ChannelDirection::ServerToClient);
+        resource!(TeamScores, ChannelDirection::ServerToClient);
+        channel!(
+            ResourceChannel,
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            }
+        );
+
+        channel!(
+            ClientChannel,
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            }
+        );
+        message!(ClientHello, ChannelDirection::ClientToServer);
+
+        channel!(
+            GameEventChannel,
+            ChannelSettings {
+                mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
+                ..default()
+            }
+        );
+        message!(GoalScored, ChannelDirection::ServerToClient);
+
+        fingerprint.add::<PlayerActions>();
         app.add_plugins(LeafwingInputPlugin::<PlayerActions>::default());
 
         // Player is synced as Simple, because we periodically update rtt ping stats
-        app.register_component::<Player>(ChannelDirection::ServerToClient)
+        component!(Player, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Simple);
 
-        app.register_component::<ColorComponent>(ChannelDirection::ServerToClient)
-            .add_prediction(ComponentSyncMode::Once);
+        // Simple, since a returning player's colour is restored once their ClientHello arrives
+        component!(ColorComponent, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(Name, ChannelDirection::ServerToClient).add_prediction(ComponentSyncMode::Once);
 
-        app.register_component::<Name>(ChannelDirection::ServerToClient)
+        component!(BallMarker, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Once);
 
-        app.register_component::<BallMarker>(ChannelDirection::ServerToClient)
+        // Simple, since health changes when shot
+        component!(Asteroid, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(BulletMarker, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Once);
 
-        app.register_component::<BulletMarker>(ChannelDirection::ServerToClient)
+        component!(Lifetime, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Once);
 
-        app.register_component::<Lifetime>(ChannelDirection::ServerToClient)
+        component!(Homing, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Once);
 
-        app.register_component::<Score>(ChannelDirection::ServerToClient)
+        component!(Pickup, ChannelDirection::ServerToClient);
+
+        // Powerup effects. Synced as Simple since they only change when collected or expired,
+        // but the predicted player needs them to predict thrust.
+        component!(Shield, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(SpeedBoost, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(Cloak, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(Score, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(Team, ChannelDirection::ServerToClient).add_prediction(ComponentSyncMode::Once);
+
+        // Simple, since the class arrives in the ClientHello, just after the ship is spawned
+        component!(ShipClass, ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Simple);
+
+        component!(LifetimeStats, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Simple);
 
         // Fully replicated, but not visual, so no need for lerp/corrections:
 
-        app.register_component::<LinearVelocity>(ChannelDirection::ServerToClient)
+        component!(LinearVelocity, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Full);
 
-        app.register_component::<AngularVelocity>(ChannelDirection::ServerToClient)
+        component!(AngularVelocity, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Full);
 
-        app.register_component::<Weapon>(ChannelDirection::ServerToClient)
+        component!(Weapon, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Full);
 
         // Position and Rotation have a `correction_fn` set, which is used to smear rollback errors
@@ -295,14 +1065,66 @@ impl Plugin for ProtocolPlugin {
         //
         // They also set `interpolation_fn` which is used by the VisualInterpolationPlugin to smooth
         // out rendering between fixedupdate ticks.
-        app.register_component::<Position>(ChannelDirection::ServerToClient)
+        component!(Position, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Full)
             .add_interpolation_fn(position::lerp)
             .add_correction_fn(position::lerp);
 
-        app.register_component::<Rotation>(ChannelDirection::ServerToClient)
+        component!(Rotation, ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Full)
             .add_interpolation_fn(rotation::lerp)
             .add_correction_fn(rotation::lerp);
+
+        let fingerprint = fingerprint.finish();
+        info!("Protocol fingerprint: {fingerprint}");
+        app.insert_resource(fingerprint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_pinned() {
+        // If this fails, the protocol changed. Check that's intended, since every client built
+        // before the change will be turned away, then update the expected value.
+        assert_eq!(
+            ProtocolFingerprint::of(PROTOCOL).to_string(),
+            "5220adb17c69f01b"
+        );
+    }
+
+    #[test]
+    fn tags_are_unique() {
+        for (i, (name, tag)) in PROTOCOL.iter().enumerate() {
+            assert!(
+                PROTOCOL[i + 1..].iter().all(|(other, _)| other != name),
+                "{name} is listed twice"
+            );
+            assert!(
+                PROTOCOL[i + 1..].iter().all(|(_, other)| other != tag),
+                "{tag} is used twice"
+            );
+        }
+    }
+
+    #[test]
+    fn renaming_keeps_fingerprint() {
+        let renamed = [("Old", "a.v1"), ("Other", "b.v1")];
+        let original = [("New", "a.v1"), ("Other", "b.v1")];
+        assert_eq!(
+            ProtocolFingerprint::of(&renamed),
+            ProtocolFingerprint::of(&original)
+        );
+    }
+
+    #[test]
+    fn order_and_versions_change_fingerprint() {
+        let original = ProtocolFingerprint::of(&[("A", "a.v1"), ("B", "b.v1")]);
+        let swapped = ProtocolFingerprint::of(&[("B", "b.v1"), ("A", "a.v1")]);
+        let bumped = ProtocolFingerprint::of(&[("A", "a.v2"), ("B", "b.v1")]);
+        assert_ne!(original, swapped);
+        assert_ne!(original, bumped);
     }
 }
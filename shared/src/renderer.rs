@@ -9,6 +9,7 @@ use bevy::core_pipeline::bloom::BloomSettings;
 use bevy::core_pipeline::tonemapping::Tonemapping;
 // use bevy::prelude::*;
 use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
 use bevy::sprite::MaterialMesh2dBundle;
 use bevy::sprite::Mesh2dHandle;
 // use bevy::time::common_conditions::on_timer;
@@ -16,6 +17,7 @@ use bevy_screen_diagnostics::ScreenEntityDiagnosticsPlugin;
 // use bevy_screen_diagnostics::ScreenFrameDiagnosticsPlugin;
 use bevy_screen_diagnostics::{Aggregate, ScreenDiagnostics, ScreenDiagnosticsPlugin};
 use leafwing_input_manager::action_state::ActionState;
+use lightyear::client::prediction::correction::Correction;
 use lightyear::client::prediction::prespawn::PreSpawnedPlayerObject;
 use lightyear::inputs::leafwing::input_buffer::InputBuffer;
 use lightyear::prelude::client::*;
@@ -47,9 +49,13 @@ impl Plugin for BLEMRendererPlugin {
             (
                 add_player_label,
                 update_player_label,
+                hide_cloaked_player_labels,
                 draw_walls,
+                add_pickup_label,
+                draw_pickups,
                 draw_confirmed_shadows.run_if(move || draw_shadows),
                 draw_predicted_entities,
+                draw_bullet_ghosts.run_if(not(is_server)),
                 draw_confirmed_entities.run_if(is_server),
                 draw_explosions,
             )
@@ -59,6 +65,11 @@ impl Plugin for BLEMRendererPlugin {
         app.add_systems(FixedPreUpdate, insert_bullet_mesh);
 
         app.add_systems(Startup, setup_diagnostic);
+        app.init_resource::<GraphicsSettings>();
+        app.add_systems(
+            Update,
+            apply_graphics_settings.run_if(resource_changed::<GraphicsSettings>),
+        );
         app.add_plugins(ScreenDiagnosticsPlugin::default());
         app.add_plugins(ScreenEntityDiagnosticsPlugin);
         // app.add_plugins(ScreenFrameDiagnosticsPlugin);
@@ -73,6 +84,37 @@ impl Plugin for BLEMRendererPlugin {
         // a Position or Rotation component.
         app.observe(add_visual_interpolation_components::<Position>);
         app.observe(add_visual_interpolation_components::<Rotation>);
+        // after FixedUpdate has recorded this frame's ticks, before PostUpdate draws between them
+        app.add_systems(
+            Update,
+            snap_wrapped_positions.run_if(|arena: Res<Arena>| arena.def.wrap.is_some()),
+        );
+    }
+}
+
+/// Visual interpolation and rollback correction both lerp `Position` between two values. When
+/// something wraps around the arena in between, that lerp would draw it sliding across the whole
+/// screen, so we make the lerp start from where it ended up instead.
+fn snap_wrapped_positions(
+    arena: Res<Arena>,
+    mut interpolated: Query<&mut VisualInterpolateStatus<Position>>,
+    mut corrected: Query<(&Position, &mut Correction<Position>)>,
+) {
+    for mut status in interpolated.iter_mut() {
+        let wrapped = match (&status.previous_value, &status.current_value) {
+            (Some(previous), Some(current)) if arena.wrapped_between(previous.0, current.0) => {
+                Some(current.clone())
+            }
+            _ => None,
+        };
+        if wrapped.is_some() {
+            status.previous_value = wrapped;
+        }
+    }
+    for (position, mut correction) in corrected.iter_mut() {
+        if arena.wrapped_between(correction.original_prediction.0, position.0) {
+            correction.original_prediction = position.clone();
+        }
     }
 }
 
@@ -110,15 +152,26 @@ fn add_visual_interpolation_components<T: Component>(
         });
 }
 
-fn init_camera(mut commands: Commands, mut windows: Query<&mut Window>) {
-    let mut window = windows.single_mut();
-    window.resolution.set(800., 800.);
+/// The camera always shows at least this much of the arena, whatever the window's shape
+const MIN_VIEW_SIZE: f32 = 800.0;
+
+fn init_camera(mut commands: Commands) {
     commands.spawn((
         Camera2dBundle {
             camera: Camera {
                 hdr: true,
                 ..default()
             },
+            // scale to fit the window, so phones and resized browser windows see the whole arena
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::AutoMin {
+                    min_width: MIN_VIEW_SIZE,
+                    min_height: MIN_VIEW_SIZE,
+                },
+                near: -1000.0,
+                far: 1000.0,
+                ..default()
+            },
             // https://bevyengine.org/examples/3D%20Rendering/tonemapping/
             // 2. Using a tonemapper that desaturates to white is recommended
             tonemapping: Tonemapping::TonyMcMapface,
@@ -180,6 +233,66 @@ fn update_player_label(
     }
 }
 
+// labels would give away the position of cloaked players
+fn hide_cloaked_player_labels(
+    mut q: Query<(&mut EntityLabel, Has<Cloak>, Has<Controlled>), With<Player>>,
+) {
+    for (mut label, cloaked, is_local) in q.iter_mut() {
+        let alpha = if cloaked && !is_local { 0.0 } else { 0.8 };
+        if label.color.alpha() != alpha {
+            label.color.set_alpha(alpha);
+        }
+    }
+}
+
+/// Graphics options, changed from the client's settings menu
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct GraphicsSettings {
+    pub bloom: bool,
+    /// The rollback and bandwidth numbers along the bottom of the screen
+    pub diagnostics: bool,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            bloom: true,
+            diagnostics: true,
+        }
+    }
+}
+
+/// The on-screen diagnostics added in [`setup_diagnostic`]
+const SCREEN_DIAGNOSTICS: [&str; 5] = ["RB", "RBt", "RBd", "KB_in", "KB_out"];
+
+fn apply_graphics_settings(
+    settings: Res<GraphicsSettings>,
+    cameras: Query<(Entity, Has<BloomSettings>), With<Camera>>,
+    mut onscreen: ResMut<ScreenDiagnostics>,
+    // screen diagnostics can only be toggled, so remember what we've shown
+    mut diagnostics_shown: Local<Option<bool>>,
+    mut commands: Commands,
+) {
+    for (camera, has_bloom) in cameras.iter() {
+        match (settings.bloom, has_bloom) {
+            (true, false) => {
+                commands.entity(camera).insert(BloomSettings::default());
+            }
+            (false, true) => {
+                commands.entity(camera).remove::<BloomSettings>();
+            }
+            _ => {}
+        }
+    }
+    let shown = diagnostics_shown.get_or_insert(true);
+    if *shown != settings.diagnostics {
+        for name in SCREEN_DIAGNOSTICS {
+            onscreen.modify(name).toggle();
+        }
+        *shown = settings.diagnostics;
+    }
+}
+
 fn setup_diagnostic(mut onscreen: ResMut<ScreenDiagnostics>) {
     onscreen
         .add("RB".to_string(), PredictionDiagnosticsPlugin::ROLLBACKS)
@@ -255,65 +368,197 @@ fn draw_predicted_entities(
             Has<PreSpawnedPlayerObject>,
             Option<&ActionState<PlayerActions>>,
             Option<&InputBuffer<PlayerActions>>,
+            Has<Shield>,
+            Has<Cloak>,
+            Has<Controlled>,
         ),
         (
             // skip drawing bullet outlines, since we add a mesh + material to them
             Without<BulletMarker>,
-            Or<(With<PreSpawnedPlayerObject>, With<Predicted>)>,
+            Or<(
+                With<PreSpawnedPlayerObject>,
+                With<Predicted>,
+                With<Interpolated>,
+            )>,
         ),
     >,
+    arena: Res<Arena>,
 ) {
-    for (_e, position, rotation, color, collider, prespawned, opt_action, opt_ib) in &predicted {
+    for (
+        _e,
+        position,
+        rotation,
+        color,
+        collider,
+        prespawned,
+        opt_action,
+        opt_ib,
+        shielded,
+        cloaked,
+        is_local,
+    ) in &predicted
+    {
         // render prespawned translucent until acknowledged by the server
         // (at which point the PreSpawnedPlayerObject component is removed)
-        let col = if prespawned {
+        let col = if cloaked {
+            // you can see a faint outline of yourself, others can barely see you at all.
+            color.0.with_alpha(if is_local { 0.3 } else { 0.05 })
+        } else if prespawned {
             color.0.with_alpha(0.5)
         } else {
             color.0
         };
 
-        render_shape(collider.shape(), position, rotation, &mut gizmos, col);
         // render engine exhaust for players holding down thrust.
-        let Some(action) = opt_action else {
-            continue;
+        let is_thrusting = match (opt_action, opt_ib) {
+            (Some(action), Some(ib)) => {
+                // if inputs are late for this player, we'll render the engine if their
+                // last input was thrust. otherwise remote players with late inputs will never
+                // appear to be thrusting, since it all happens in rollback.
+                PlayerActions::throttle(action) > 0.0
+                    || ib
+                        .get_last()
+                        .is_some_and(|action| PlayerActions::throttle(action) > 0.0)
+            }
+            _ => false,
         };
-        let Some(ib) = opt_ib else {
-            continue;
+        // the shield and exhaust stick out past a ship's collider
+        let margin = if opt_action.is_some() {
+            bounding_radius(collider).max(SHIP_LENGTH * 0.8)
+        } else {
+            bounding_radius(collider)
         };
-        let mut is_thrusting = action.pressed(&PlayerActions::Up);
-        if !is_thrusting {
-            // if inputs are late for this player, we'll render the engine if their
-            // last input was thrust. otherwise remote players with late inputs will never
-            // appear to be thrusting, since it all happens in rollback.
-            if let Some(action) = ib.get_last() {
-                is_thrusting = action.pressed(&PlayerActions::Up);
+
+        for position in wrapped_positions(&arena, position, margin) {
+            render_shape(collider.shape(), &position, rotation, &mut gizmos, col);
+
+            if shielded && !(cloaked && !is_local) {
+                gizmos.circle_2d(position.0, SHIP_LENGTH * 0.8, css::AQUA.with_alpha(0.6));
+            }
+            if is_thrusting {
+                render_shape(
+                    exhaust_collider().shape(),
+                    &position,
+                    rotation,
+                    &mut gizmos,
+                    (col.to_linear() * 2.5).into(), // bloom
+                );
             }
         }
+    }
+}
 
-        if is_thrusting {
-            // draw an engine exhaust triangle
-            let width = 0.6 * (SHIP_WIDTH / 2.0);
-            let points = vec![
-                Vec2::new(width, (-SHIP_LENGTH / 2.) - 3.0),
-                Vec2::new(-width, (-SHIP_LENGTH / 2.) - 3.0),
-                Vec2::new(0.0, (-SHIP_LENGTH / 2.) - 10.0),
-            ];
-            let collider = Collider::convex_hull(points).unwrap();
-            render_shape(
-                collider.shape(),
-                position,
-                rotation,
-                &mut gizmos,
-                (col.to_linear() * 2.5).into(), // bloom
-            );
+/// Bullets are drawn with a mesh, which can't be in two places at once, so in wrap-around arenas
+/// this draws their copies poking out of the opposite edge.
+fn draw_bullet_ghosts(
+    mut gizmos: Gizmos,
+    bullets: Query<
+        (&Position, &Collider, &ColorComponent),
+        (With<BulletMarker>, Without<Confirmed>),
+    >,
+    arena: Res<Arena>,
+) {
+    if arena.def.wrap.is_none() {
+        return;
+    }
+    for (position, collider, color) in &bullets {
+        let radius = bounding_radius(collider);
+        for offset in arena.ghost_offsets(position.0, radius) {
+            gizmos.circle_2d(position.0 + offset, radius, color.0);
         }
     }
 }
 
-fn draw_walls(walls: Query<&Wall, Without<Player>>, mut gizmos: Gizmos) {
+/// In a wrap-around arena, something within `margin` of an edge pokes out of the opposite edge
+/// too. Returns its position, followed by any of those copies.
+fn wrapped_positions(
+    arena: &Arena,
+    position: &Position,
+    margin: f32,
+) -> impl Iterator<Item = Position> {
+    let p = position.0;
+    std::iter::once(Vec2::ZERO)
+        .chain(arena.ghost_offsets(p, margin))
+        .map(move |offset| Position(p + offset))
+}
+
+/// How far a collider reaches from the entity's centre
+fn bounding_radius(collider: &Collider) -> f32 {
+    collider.shape().compute_local_bounding_sphere().radius()
+}
+
+/// The engine exhaust triangle drawn behind thrusting ships
+fn exhaust_collider() -> Collider {
+    let width = 0.6 * (SHIP_WIDTH / 2.0);
+    let points = vec![
+        Vec2::new(width, (-SHIP_LENGTH / 2.) - 3.0),
+        Vec2::new(-width, (-SHIP_LENGTH / 2.) - 3.0),
+        Vec2::new(0.0, (-SHIP_LENGTH / 2.) - 10.0),
+    ];
+    Collider::convex_hull(points).unwrap()
+}
+
+fn draw_walls(
+    walls: Query<&Wall, Without<Player>>,
+    obstacles: Query<(&Obstacle, &Position, &ColorComponent)>,
+    goals: Query<(&GoalZone, &Position)>,
+    arena: Res<Arena>,
+    mut gizmos: Gizmos,
+) {
     for wall in &walls {
         gizmos.line_2d(wall.start, wall.end, Color::WHITE);
     }
+    // faint outline where wrap-around arenas wrap
+    if let Some(half) = arena.def.wrap {
+        let corners = [
+            Vec2::new(-half.x, -half.y),
+            Vec2::new(-half.x, half.y),
+            Vec2::new(half.x, half.y),
+            Vec2::new(half.x, -half.y),
+        ];
+        for (start, end) in corners.iter().zip(corners.iter().cycle().skip(1)) {
+            gizmos.line_2d(*start, *end, Color::WHITE.with_alpha(0.1));
+        }
+    }
+    for (obstacle, position, color) in &obstacles {
+        gizmos.circle_2d(position.0, obstacle.radius, color.0);
+    }
+    for (goal, position) in &goals {
+        let color = goal.team.color();
+        gizmos.circle_2d(position.0, goal.radius, color);
+        gizmos.circle_2d(position.0, goal.radius * 0.9, color.with_alpha(0.3));
+    }
+}
+
+fn add_pickup_label(mut commands: Commands, q: Query<(Entity, &Pickup, &Position), Added<Pickup>>) {
+    for (e, pickup, position) in q.iter() {
+        // pickups don't move, so we can set the transform once.
+        commands.entity(e).insert((
+            VisibilityBundle::default(),
+            TransformBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+            EntityLabel {
+                text: pickup.kind.name().to_string(),
+                size: 11.0,
+                offset: Vec2::Y * -(PICKUP_RADIUS + 12.0),
+                ..Default::default()
+            },
+        ));
+    }
+}
+
+fn draw_pickups(q: Query<(&Pickup, &Position)>, time: Res<Time>, mut gizmos: Gizmos) {
+    // gently pulsing, so they stand out from balls
+    let pulse = 0.8 + 0.2 * (time.elapsed_seconds() * 4.0).sin();
+    for (pickup, position) in q.iter() {
+        let color: Color = match pickup.kind {
+            PickupKind::Weapon(_) => css::ORANGE_RED.into(),
+            PickupKind::Powerup(PowerupKind::Shield) => css::AQUA.into(),
+            PickupKind::Powerup(PowerupKind::SpeedBoost) => css::LIME.into(),
+            PickupKind::Powerup(PowerupKind::Cloak) => css::MEDIUM_PURPLE.into(),
+        };
+        gizmos.circle_2d(position.0, PICKUP_RADIUS * pulse, color);
+        gizmos.circle_2d(position.0, PICKUP_RADIUS * 0.5 * pulse, color);
+    }
 }
 
 /// Draws confirmed entities that have colliders.
@@ -332,22 +577,22 @@ fn draw_confirmed_entities(
         ),
         Or<(With<Player>, With<BallMarker>, With<BulletMarker>)>,
     >,
+    arena: Res<Arena>,
 ) {
     for (position, rotation, color, collider, opt_action, col) in &confirmed {
-        render_shape(collider.shape(), position, rotation, &mut gizmos, color.0);
-        // render engine exhaust for players holding down thrust.
-        if let Some(action) = opt_action {
-            if action.pressed(&PlayerActions::Up) {
-                let width = 0.6 * (SHIP_WIDTH / 2.0);
-                let points = vec![
-                    Vec2::new(width, (-SHIP_LENGTH / 2.) - 3.0),
-                    Vec2::new(-width, (-SHIP_LENGTH / 2.) - 3.0),
-                    Vec2::new(0.0, (-SHIP_LENGTH / 2.) - 10.0),
-                ];
-                let collider = Collider::convex_hull(points).unwrap();
+        let is_thrusting = opt_action.is_some_and(|action| PlayerActions::throttle(action) > 0.0);
+        let margin = if is_thrusting {
+            bounding_radius(collider).max(SHIP_LENGTH * 0.8)
+        } else {
+            bounding_radius(collider)
+        };
+        for position in wrapped_positions(&arena, position, margin) {
+            render_shape(collider.shape(), &position, rotation, &mut gizmos, color.0);
+            // render engine exhaust for players holding down thrust.
+            if is_thrusting {
                 render_shape(
-                    collider.shape(),
-                    position,
+                    exhaust_collider().shape(),
+                    &position,
                     rotation,
                     &mut gizmos,
                     col.0.with_alpha(0.7),
@@ -457,6 +702,13 @@ impl Explosion {
         }
     }
 
+    /// A bigger, longer lasting explosion, for things like goals
+    pub fn with_size(mut self, radius: f32, max_age: Duration) -> Self {
+        self.initial_radius = radius;
+        self.max_age = max_age;
+        self
+    }
+
     // Gives a color and radius based on elapsed time, for a simple visual explosion effect.
     //
     // None = despawn due to expiry.
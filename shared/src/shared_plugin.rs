@@ -11,8 +11,14 @@ impl Plugin for BevygapSpaceshipsSharedPlugin {
         if app.is_plugin_added::<bevy::render::RenderPlugin>() {
             app.add_plugins(BLEMRendererPlugin);
         }
-        // bundles
-        app.add_systems(Startup, init);
+        // arena geometry is rebuilt whenever the Arena resource changes.
+        // the server may choose a different arena, and clients load whichever the server uses.
+        app.insert_resource(Arena::load(DEFAULT_ARENA).expect("Default arena should always load"));
+        app.add_systems(
+            PreUpdate,
+            spawn_arena_geometry
+                .run_if(resource_changed::<Arena>.or_else(resource_changed::<MatchRules>)),
+        );
         // Physics
         //
         // we use Position and Rotation as primary source of truth, so no need to sync changes
@@ -49,7 +55,14 @@ impl Plugin for BevygapSpaceshipsSharedPlugin {
         );
         app.add_systems(
             FixedUpdate,
-            (process_collisions, lifetime_despawner).in_set(FixedSet::Main),
+            (process_collisions, lifetime_despawner, steer_homing_bullets).in_set(FixedSet::Main),
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            wrap_positions
+                .after(FixedSet::Physics)
+                .run_if(|arena: Res<Arena>| arena.def.wrap.is_some()),
         );
 
         app.add_systems(PostProcessCollisions, filter_own_bullet_collisions);
@@ -68,50 +81,44 @@ pub fn color_from_id(client_id: ClientId) -> Color {
     Color::hsl(h, s, l)
 }
 
-fn init(mut commands: Commands) {
-    commands.spawn(WallBundle::new(
-        Vec2::new(-WALL_SIZE, -WALL_SIZE),
-        Vec2::new(-WALL_SIZE, WALL_SIZE),
-        Color::WHITE,
-    ));
-    commands.spawn(WallBundle::new(
-        Vec2::new(-WALL_SIZE, WALL_SIZE),
-        Vec2::new(WALL_SIZE, WALL_SIZE),
-        Color::WHITE,
-    ));
-    commands.spawn(WallBundle::new(
-        Vec2::new(WALL_SIZE, WALL_SIZE),
-        Vec2::new(WALL_SIZE, -WALL_SIZE),
-        Color::WHITE,
-    ));
-    commands.spawn(WallBundle::new(
-        Vec2::new(WALL_SIZE, -WALL_SIZE),
-        Vec2::new(-WALL_SIZE, -WALL_SIZE),
-        Color::WHITE,
-    ));
-}
-
 // Players can't collide with their own bullets.
 // this is especially helpful if you are accelerating forwards while shooting, as otherwise you
 // might overtake / collide on spawn with your own bullets that spawn in front of you.
+//
+// Without friendly fire, bullets also pass through teammates.
+//
+// Bullets also fly straight through pickups.
 fn filter_own_bullet_collisions(
     mut collisions: ResMut<Collisions>,
     q_bullets: Query<&BulletMarker>,
-    q_players: Query<&Player>,
+    q_players: Query<(&Player, Option<&Team>)>,
+    q_pickups: Query<(), With<Pickup>>,
+    rules: Res<MatchRules>,
 ) {
+    let team_of = |client_id: ClientId| {
+        q_players
+            .iter()
+            .find(|(player, _)| player.client_id == client_id)
+            .and_then(|(_, team)| team)
+    };
+    let bullet_passes_through = |bullet: &BulletMarker, other: Entity| {
+        if q_pickups.contains(other) {
+            return true;
+        }
+        let Ok((player, team)) = q_players.get(other) else {
+            return false;
+        };
+        bullet.owner == player.client_id || !rules.can_hit(team_of(bullet.owner), team)
+    };
     collisions.retain(|contacts| {
         if let Ok(bullet) = q_bullets.get(contacts.entity1) {
-            if let Ok(player) = q_players.get(contacts.entity2) {
-                if bullet.owner == player.client_id {
-                    return false;
-                }
+            if bullet_passes_through(bullet, contacts.entity2) {
+                return false;
             }
         }
         if let Ok(bullet) = q_bullets.get(contacts.entity2) {
-            if let Ok(player) = q_players.get(contacts.entity1) {
-                if bullet.owner == player.client_id {
-                    return false;
-                }
+            if bullet_passes_through(bullet, contacts.entity1) {
+                return false;
             }
         }
         true
@@ -141,6 +148,8 @@ pub struct ApplyInputsQuery {
     pub ang_vel: &'static mut AngularVelocity,
     pub rot: &'static Rotation,
     pub player: &'static Player,
+    pub ship_class: &'static ShipClass,
+    pub speed_boost: Option<&'static SpeedBoost>,
 }
 
 /// applies forces based on action state inputs
@@ -154,18 +163,30 @@ pub fn apply_action_state_to_player_movement(
     let rot = &aiq.rot;
     let ang_vel = &mut aiq.ang_vel;
 
-    const THRUSTER_POWER: f32 = 32000.;
-    const ROTATIONAL_SPEED: f32 = 4.0;
+    let ship = aiq.ship_class.def();
 
-    if action.pressed(&PlayerActions::Up) {
+    let thrust = if aiq.speed_boost.is_some() {
+        ship.thrust * SPEED_BOOST_MULTIPLIER
+    } else {
+        ship.thrust
+    };
+
+    let throttle = PlayerActions::throttle(action);
+    if throttle > 0.0 {
         ex_force
-            .apply_force(*rot * (Vec2::Y * THRUSTER_POWER))
+            .apply_force(*rot * (Vec2::Y * thrust * throttle))
             .with_persistence(false);
     }
+    let aim = action.axis_pair(&PlayerActions::Aim);
     let desired_ang_vel = if action.pressed(&PlayerActions::Left) {
-        ROTATIONAL_SPEED
+        ship.turn_rate
     } else if action.pressed(&PlayerActions::Right) {
-        -ROTATIONAL_SPEED
+        -ship.turn_rate
+    } else if aim.length() > AIM_DEADZONE {
+        // turn towards the aim direction, slowing down as we line up so we don't overshoot
+        const AIM_TURN_GAIN: f32 = 10.0;
+        let facing = *rot * Vec2::Y;
+        (facing.angle_between(aim) * AIM_TURN_GAIN).clamp(-ship.turn_rate, ship.turn_rate)
     } else {
         0.0
     };
@@ -195,6 +216,7 @@ pub fn shared_player_firing(
             &mut Weapon,
             Has<Controlled>,
             &Player,
+            &ShipClass,
         ),
         Or<(With<Predicted>, With<ReplicationTarget>)>,
     >,
@@ -216,13 +238,36 @@ pub fn shared_player_firing(
         mut weapon,
         _is_local,
         player,
+        ship_class,
     ) in q.iter_mut()
     {
-        if !action.pressed(&PlayerActions::Fire) {
+        let ship = ship_class.def();
+        if weapon.is_expired(current_tick) {
+            info!("{:?} weapon expired for {}", weapon.kind, player.client_id);
+            *weapon = Weapon::new(ship.weapon);
+        }
+
+        let wants_to_fire = match weapon.kind {
+            // charged weapons fire when the trigger is released after charging up
+            WeaponKind::ChargedRail => {
+                if action.pressed(&PlayerActions::Fire) {
+                    weapon.charge = (weapon.charge + 1).min(RAIL_CHARGE_TICKS);
+                    false
+                } else if weapon.charge > 0 {
+                    let charged = weapon.charge >= RAIL_CHARGE_TICKS;
+                    weapon.charge = 0;
+                    charged
+                } else {
+                    false
+                }
+            }
+            _ => action.pressed(&PlayerActions::Fire),
+        };
+        if !wants_to_fire {
             continue;
         }
         let wrapped_diff = weapon.last_fire_tick - current_tick;
-        if wrapped_diff.abs() <= weapon.cooldown as i16 {
+        if wrapped_diff.abs() <= weapon.kind.cooldown() as i16 {
             // cooldown period - can't fire.
             if weapon.last_fire_tick == current_tick {
                 // logging because debugging latency edge conditions where
@@ -238,45 +283,92 @@ pub fn shared_player_firing(
 
         // bullet spawns just in front of the nose of the ship, in the direction the ship is facing,
         // and inherits the speed of the ship.
-        let bullet_spawn_offset = Vec2::Y * (2.0 + (SHIP_LENGTH + BULLET_SIZE) / 2.0);
-
-        let bullet_origin = player_position.0 + player_rotation * bullet_spawn_offset;
-        let bullet_linvel = player_rotation * (Vec2::Y * weapon.bullet_speed) + player_velocity.0;
-
-        // the default hashing algorithm uses the tick and component list. in order to disambiguate
-        // between two players spawning a bullet on the same tick, we add client_id to the mix.
-        let prespawned = PreSpawnedPlayerObject::default_with_salt(player.client_id.to_bits());
-
-        let bullet_entity = commands
-            .spawn((
-                BulletBundle::new(
-                    player.client_id,
-                    bullet_origin,
-                    bullet_linvel,
-                    (color.0.to_linear() * 5.0).into(), // bloom!
-                    current_tick,
-                ),
-                PhysicsBundle::bullet(),
-                prespawned,
-            ))
-            .id();
-        info!(
-            "spawned bullet for ActionState, bullet={bullet_entity:?} ({}, {}). prev last_fire tick: {prev_last_fire_tick:?}",
-            weapon.last_fire_tick.0, player.client_id
-        );
+        let bullet_spawn_offset = Vec2::Y * (2.0 + (ship.length + BULLET_SIZE) / 2.0);
 
-        if identity.is_server() {
-            let replicate = server::Replicate {
-                sync: server::SyncTarget {
-                    prediction: NetworkTarget::All,
-                    ..Default::default()
-                },
-                // make sure that all entities that are predicted are part of the same replication group
-                group: REPLICATION_GROUP,
-                ..default()
-            };
-            commands.entity(bullet_entity).insert(replicate);
+        for (index, angle) in weapon.kind.projectile_angles().iter().enumerate() {
+            let bullet_rotation = *player_rotation * Rotation::radians(*angle);
+            let bullet_origin = player_position.0 + bullet_rotation * bullet_spawn_offset;
+            let bullet_linvel =
+                bullet_rotation * (Vec2::Y * weapon.kind.bullet_speed()) + player_velocity.0;
+
+            // the default hashing algorithm uses the tick and component list. in order to disambiguate
+            // between two players spawning a bullet on the same tick, we add client_id to the mix.
+            // weapons that fire several bullets at once also mix in the bullet index.
+            let salt = player.client_id.to_bits() ^ ((index as u64) << 56);
+            let prespawned = PreSpawnedPlayerObject::default_with_salt(salt);
+
+            let bullet_entity = commands
+                .spawn((
+                    BulletBundle::new(
+                        player.client_id,
+                        bullet_origin,
+                        bullet_linvel,
+                        (color.0.to_linear() * 5.0).into(), // bloom!
+                        current_tick,
+                    ),
+                    PhysicsBundle::bullet(),
+                    prespawned,
+                ))
+                .id();
+            if weapon.kind == WeaponKind::HomingMissile {
+                commands
+                    .entity(bullet_entity)
+                    .insert(Homing { turn_rate: 0.06 });
+            }
+            info!(
+                "spawned bullet for ActionState, bullet={bullet_entity:?} ({}, {}). prev last_fire tick: {prev_last_fire_tick:?}",
+                weapon.last_fire_tick.0, player.client_id
+            );
+
+            if identity.is_server() {
+                let replicate = server::Replicate {
+                    sync: server::SyncTarget {
+                        prediction: NetworkTarget::All,
+                        ..Default::default()
+                    },
+                    // make sure that all entities that are predicted are part of the same replication group
+                    group: REPLICATION_GROUP,
+                    ..default()
+                };
+                commands.entity(bullet_entity).insert(replicate);
+            }
+        }
+    }
+}
+
+/// Homing bullets turn towards the nearest ship that isn't their owner, or a teammate.
+pub fn steer_homing_bullets(
+    mut bullets: Query<(&BulletMarker, &Homing, &Position, &mut LinearVelocity)>,
+    players: Query<
+        (&Player, &Position, Option<&Team>),
+        Or<(With<Predicted>, With<ReplicationTarget>)>,
+    >,
+) {
+    for (bullet, homing, bullet_pos, mut velocity) in bullets.iter_mut() {
+        let owner_team = players
+            .iter()
+            .find(|(player, _, _)| player.client_id == bullet.owner)
+            .and_then(|(_, _, team)| team);
+        let target = players
+            .iter()
+            .filter(|(player, _, team)| {
+                player.client_id != bullet.owner && (owner_team.is_none() || *team != owner_team)
+            })
+            .map(|(_, pos, _)| pos.0)
+            .min_by(|a, b| {
+                a.distance_squared(bullet_pos.0)
+                    .total_cmp(&b.distance_squared(bullet_pos.0))
+            });
+        let Some(target) = target else {
+            continue;
+        };
+        let desired = target - bullet_pos.0;
+        if desired == Vec2::ZERO || velocity.0 == Vec2::ZERO {
+            continue;
         }
+        let angle = velocity.0.angle_between(desired);
+        let turn = angle.clamp(-homing.turn_rate, homing.turn_rate);
+        velocity.0 = Vec2::from_angle(turn).rotate(velocity.0);
     }
 }
 
@@ -367,6 +459,7 @@ pub fn process_collisions(
             let ev = BulletHitEvent {
                 bullet_owner: bullet.owner,
                 victim_client_id,
+                target: contacts.entity2,
                 position: bullet_pos.0,
                 bullet_color: col.0,
             };
@@ -388,6 +481,7 @@ pub fn process_collisions(
             let ev = BulletHitEvent {
                 bullet_owner: bullet.owner,
                 victim_client_id,
+                target: contacts.entity1,
                 position: bullet_pos.0,
                 bullet_color: col.0,
             };
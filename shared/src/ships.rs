@@ -0,0 +1,110 @@
+//! Ship classes. Each class is just data: hull shape, mass, engine and starting weapon.
+//!
+//! Players choose a class on the connect screen, and tell the server in their [`ClientHello`].
+//! The class is replicated as a component, so clients predict the right physics for every ship.
+use crate::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ShipClass {
+    /// All-rounder, the original ship
+    #[default]
+    Fighter,
+    /// Heavy and slow to turn, but hard to push around, with a spread gun
+    Tank,
+    /// Light and nimble, with a rapid fire gun
+    Scout,
+}
+
+/// Everything that differs between ship classes
+#[derive(Debug)]
+pub struct ShipClassDef {
+    pub name: &'static str,
+    /// Hull outline, pointing up the screen
+    pub hull: &'static [Vec2],
+    /// Nose to tail, used to place bullets just in front of the nose
+    pub length: f32,
+    pub density: f32,
+    pub thrust: f32,
+    /// Radians per second
+    pub turn_rate: f32,
+    /// The weapon the ship starts with, and reverts to when a pickup weapon expires
+    pub weapon: WeaponKind,
+}
+
+const FIGHTER: ShipClassDef = ShipClassDef {
+    name: "Fighter",
+    hull: &[
+        Vec2::new(0.0, SHIP_LENGTH / 2.),
+        Vec2::new(-SHIP_WIDTH / 2., -SHIP_LENGTH / 2.),
+        Vec2::new(SHIP_WIDTH / 2., -SHIP_LENGTH / 2.),
+    ],
+    length: SHIP_LENGTH,
+    density: 1.0,
+    thrust: 32000.,
+    turn_rate: 4.0,
+    weapon: WeaponKind::Standard,
+};
+
+const TANK: ShipClassDef = ShipClassDef {
+    name: "Tank",
+    // blunt nosed and wide
+    hull: &[
+        Vec2::new(-6.0, 16.0),
+        Vec2::new(-14.0, -4.0),
+        Vec2::new(-14.0, -16.0),
+        Vec2::new(14.0, -16.0),
+        Vec2::new(14.0, -4.0),
+        Vec2::new(6.0, 16.0),
+    ],
+    length: 32.0,
+    density: 2.0,
+    thrust: 72000.,
+    turn_rate: 2.8,
+    weapon: WeaponKind::SpreadShot,
+};
+
+const SCOUT: ShipClassDef = ShipClassDef {
+    name: "Scout",
+    // a narrow dart
+    hull: &[
+        Vec2::new(0.0, 14.0),
+        Vec2::new(-6.0, -12.0),
+        Vec2::new(6.0, -12.0),
+    ],
+    length: 26.0,
+    density: 0.8,
+    thrust: 16000.,
+    turn_rate: 5.5,
+    weapon: WeaponKind::RapidFire,
+};
+
+impl ShipClass {
+    pub const ALL: [ShipClass; 3] = [ShipClass::Fighter, ShipClass::Tank, ShipClass::Scout];
+
+    pub fn def(&self) -> &'static ShipClassDef {
+        match self {
+            ShipClass::Fighter => &FIGHTER,
+            ShipClass::Tank => &TANK,
+            ShipClass::Scout => &SCOUT,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.def().name
+    }
+
+    pub fn physics_bundle(&self) -> PhysicsBundle {
+        let def = self.def();
+        // Note: due to a bug in older (?) versions of bevy_xpbd, using a triangle collider here
+        // sometimes caused strange behaviour. Unsure if this is fixed now.
+        let collider = Collider::convex_hull(def.hull.to_vec()).unwrap();
+        PhysicsBundle {
+            collider,
+            collider_density: ColliderDensity(def.density),
+            rigid_body: RigidBody::Dynamic,
+            external_force: ExternalForce::ZERO.with_persistence(false),
+        }
+    }
+}
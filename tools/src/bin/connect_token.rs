@@ -0,0 +1,70 @@
+//! Mints a lightyear connect token for a local server, like the bevygap matchmaker would.
+//!
+//! ```text
+//! cargo run -p tools --bin connect_token -- --out token.txt
+//! LIGHTYEAR_CONNECT_TOKEN_FILE=token.txt cargo run --no-default-features -p client
+//! ```
+use clap::Parser;
+use shared::prelude::*;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tools::*;
+
+#[derive(Parser, Debug)]
+#[command(about = "Mints a lightyear connect token, using LIGHTYEAR_PRIVATE_KEY")]
+struct Args {
+    /// The game server the token is for
+    #[arg(long, default_value_t = SocketAddr::from(([127, 0, 0, 1], SERVER_PORT)))]
+    server_addr: SocketAddr,
+    /// Defaults to a fresh id, tokens are single use anyway
+    #[arg(long)]
+    client_id: Option<u64>,
+    /// How long the token can be used to start connecting
+    #[arg(long, default_value_t = 300)]
+    expire_secs: i32,
+    /// How long the connection may go without hearing from the server
+    #[arg(long, default_value_t = 10)]
+    timeout_secs: i32,
+    /// Write the token to this file instead of stdout
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let request = TokenRequest {
+        server_addr: args.server_addr,
+        client_id: args.client_id.unwrap_or_else(fresh_client_id),
+        expire_secs: args.expire_secs,
+        timeout_secs: args.timeout_secs,
+    };
+    let private_key = match private_key() {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let token = match mint_connect_token(&request, private_key) {
+        Ok(token) => token,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!(
+        "Minted token for client {} to connect to {}, expires in {}s",
+        request.client_id, request.server_addr, request.expire_secs
+    );
+    match args.out {
+        Some(path) => {
+            if let Err(e) = std::fs::write(&path, &token) {
+                eprintln!("Failed to write {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+        None => println!("{token}"),
+    }
+    ExitCode::SUCCESS
+}
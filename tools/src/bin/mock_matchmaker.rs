@@ -0,0 +1,185 @@
+//! A stand-in for the bevygap matchmaker, for testing the client's bevygap connect flow without
+//! NATS or Edgegap. Every request gets a connect token for one locally running game server.
+//!
+//! ```text
+//! cargo run --no-default-features -p server
+//! cargo run -p tools --bin mock_matchmaker
+//! cargo run -p client
+//! ```
+//!
+//! `--delay-ms`, `--error-code`, `--error-every` and `--hang` simulate a slow or failing
+//! matchmaker, to exercise the connect screen's `BevygapClientState` transitions.
+use clap::Parser;
+use shared::prelude::*;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tools::matchmaker::*;
+use tools::*;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::Message;
+
+#[derive(Parser, Debug)]
+#[command(about = "Hands out connect tokens for a local server, like the bevygap matchmaker")]
+struct Args {
+    /// Where to listen for clients, the client's default MATCHMAKER_URL points here
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    listen: SocketAddr,
+    /// The game server clients are sent to
+    #[arg(long, default_value_t = SocketAddr::from(([127, 0, 0, 1], SERVER_PORT)))]
+    server_addr: SocketAddr,
+    /// The game server's certificate digest, which it logs at startup. Only wasm clients need it.
+    #[arg(long, default_value = "")]
+    cert_digest: String,
+    #[arg(long, default_value = "bevygap-spaceships")]
+    game_name: String,
+    /// Must match the client's `GAME_VERSION`
+    #[arg(long, default_value = "1")]
+    game_version: String,
+    /// Wait this long before each reply
+    #[arg(long, default_value_t = 500)]
+    delay_ms: u64,
+    /// Fail requests with this error code instead of handing out tokens
+    #[arg(long)]
+    error_code: Option<u16>,
+    /// With --error-code, only fail every Nth request
+    #[arg(long, default_value_t = 1)]
+    error_every: u64,
+    /// Stop replying after the first status update, as if matchmaking never finished
+    #[arg(long)]
+    hang: bool,
+}
+
+struct Matchmaker {
+    args: Args,
+    private_key: [u8; PRIVATE_KEY_BYTES],
+    requests: AtomicU64,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let private_key = match private_key() {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let listener = match TcpListener::bind(args.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {e}", args.listen);
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!(
+        "Mock matchmaker on ws://{}{MATCHMAKER_PATH}, sending clients to {}",
+        args.listen, args.server_addr
+    );
+    let matchmaker = Arc::new(Matchmaker {
+        args,
+        private_key,
+        requests: AtomicU64::new(0),
+    });
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Accept failed: {e}");
+                continue;
+            }
+        };
+        let matchmaker = matchmaker.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().ok();
+            if let Err(e) = matchmaker.handle_client(stream) {
+                eprintln!("Client {peer:?}: {e}");
+            }
+        });
+    }
+    ExitCode::SUCCESS
+}
+
+/// Only the matchmaker path speaks websocket, like the real http server
+fn check_path(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    if request.uri().path() == MATCHMAKER_PATH {
+        return Ok(response);
+    }
+    let mut not_found = ErrorResponse::new(Some("Not found".to_string()));
+    *not_found.status_mut() = tungstenite::http::StatusCode::NOT_FOUND;
+    Err(not_found)
+}
+
+impl Matchmaker {
+    fn handle_client(&self, stream: TcpStream) -> Result<(), String> {
+        let mut ws = tungstenite::accept_hdr(stream, check_path).map_err(|e| e.to_string())?;
+        let text = loop {
+            match ws.read().map_err(|e| e.to_string())? {
+                Message::Text(text) => break text,
+                Message::Close(_) => return Ok(()),
+                _ => continue,
+            }
+        };
+        let number = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        eprintln!("Request #{number}: {text}");
+
+        for reply in self.replies(number, MatchRequest::from_json(&text)) {
+            std::thread::sleep(Duration::from_millis(self.args.delay_ms));
+            eprintln!("Reply #{number}: {reply:?}");
+            ws.send(Message::Text(reply.to_json()))
+                .map_err(|e| e.to_string())?;
+            if reply.is_final() {
+                let _ = ws.close(None);
+                let _ = ws.flush();
+                return Ok(());
+            }
+        }
+        // hanging, wait for the client to give up
+        while ws.read().is_ok() {}
+        Ok(())
+    }
+
+    /// Everything we'll send in answer to a request, ending with a final reply unless hanging
+    fn replies(&self, number: u64, request: Result<MatchRequest, String>) -> Vec<MatchResponse> {
+        let args = &self.args;
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => return vec![MatchResponse::error(400, e)],
+        };
+        if request.game != args.game_name || request.version != args.game_version {
+            return vec![MatchResponse::error(
+                400,
+                format!(
+                    "No servers for {} version {}",
+                    request.game, request.version
+                ),
+            )];
+        }
+        let mut replies = vec![MatchResponse::status("Finding a server...")];
+        if args.hang {
+            return replies;
+        }
+        if let Some(code) = args.error_code {
+            if number % args.error_every.max(1) == 0 {
+                replies.push(MatchResponse::error(code, "Simulated matchmaker error"));
+                return replies;
+            }
+        }
+        replies.push(MatchResponse::status(
+            "Server found, preparing connect token...",
+        ));
+        let token_request = TokenRequest::new(args.server_addr, fresh_client_id());
+        replies.push(match mint_connect_token(&token_request, self.private_key) {
+            Ok(token) => MatchResponse::Ready {
+                token,
+                ip: args.server_addr.ip().to_string(),
+                port: args.server_addr.port(),
+                cert_digest: args.cert_digest.clone(),
+            },
+            Err(e) => MatchResponse::error(500, e.to_string()),
+        });
+        replies
+    }
+}
@@ -0,0 +1,87 @@
+//! Tools for running the game locally without the bevygap matchmaker, NATS or Edgegap.
+//!
+//! Connect tokens are what the matchmaker hands to clients, so minting our own lets us test
+//! `Authentication::Token` against a local server. The mock matchmaker goes further, handing
+//! them out over the same websocket protocol as the real matchmaker.
+use base64::prelude::*;
+use lightyear::connection::netcode::ConnectToken;
+use shared::prelude::*;
+use std::fmt;
+use std::net::SocketAddr;
+
+pub mod matchmaker;
+
+/// Who a connect token is for, and how long it lasts
+#[derive(Debug, Clone)]
+pub struct TokenRequest {
+    /// The game server the client may connect to
+    pub server_addr: SocketAddr,
+    pub client_id: u64,
+    /// How long the client has to start connecting with the token
+    pub expire_secs: i32,
+    /// How long the connection may go without hearing from the server
+    pub timeout_secs: i32,
+}
+
+impl TokenRequest {
+    pub fn new(server_addr: SocketAddr, client_id: u64) -> Self {
+        Self {
+            server_addr,
+            client_id,
+            expire_secs: 300,
+            timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Generate(String),
+    Encode(std::io::Error),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Generate(e) => write!(f, "failed to generate connect token: {e}"),
+            TokenError::Encode(e) => write!(f, "failed to encode connect token: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// The key the server uses: `LIGHTYEAR_PRIVATE_KEY`, or the zeroed key it falls back to.
+pub fn private_key() -> Result<[u8; PRIVATE_KEY_BYTES], PrivateKeyError> {
+    Ok(read_lightyear_private_key_from_env()?.unwrap_or_else(|| {
+        eprintln!("{PRIVATE_KEY_ENV} not set, using dummy key");
+        DUMMY_PRIVATE_KEY
+    }))
+}
+
+/// Mints a connect token, returning it base64 encoded, as the client expects it.
+pub fn mint_connect_token(
+    request: &TokenRequest,
+    private_key: [u8; PRIVATE_KEY_BYTES],
+) -> Result<String, TokenError> {
+    let token = ConnectToken::build(
+        request.server_addr,
+        PROTOCOL_ID,
+        request.client_id,
+        private_key,
+    )
+    .expire_seconds(request.expire_secs)
+    .timeout_seconds(request.timeout_secs)
+    .generate()
+    .map_err(|e| TokenError::Generate(e.to_string()))?;
+    let bytes = token.try_into_bytes().map_err(TokenError::Encode)?;
+    Ok(BASE64_STANDARD.encode(bytes))
+}
+
+/// A client id that won't clash with other clients minted around the same time.
+pub fn fresh_client_id() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
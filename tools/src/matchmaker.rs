@@ -0,0 +1,146 @@
+//! Messages for the matchmaker websocket, as spoken by `bevygap_client_plugin`.
+//!
+//! bevygap doesn't publish these types, so they're written by hand and must be kept in line with
+//! the client plugin and the matchmaker's websocket handler at the tag we depend on:
+//!
+//! * <https://github.com/RJ/bevygap/tree/v0.1.7/bevygap_client_plugin/src>
+//! * <https://github.com/RJ/bevygap/tree/v0.1.7/bevygap_matchmaker_httpd/src>
+//!
+//! They haven't been checked field by field against those sources yet, so if the client doesn't
+//! get past `AwaitingResponse` with the mock, compare them first. Everything the mock matchmaker
+//! knows about the wire format is in this module, so this is the one place to update.
+//!
+//! The flow over one websocket connection:
+//!
+//! 1. The client sends a [`MatchRequest`] naming the game and version it wants a server for.
+//! 2. The matchmaker sends any number of [`MatchResponse::Status`] updates, which the client shows
+//!    as `BevygapClientState::AwaitingResponse`.
+//! 3. The matchmaker finishes with either [`MatchResponse::Error`], which becomes
+//!    `BevygapClientState::Error`, or [`MatchResponse::Ready`], carrying the connect token and
+//!    where to connect with it, after which the client goes to `ReadyToConnect`.
+use serde::{Deserialize, Serialize};
+
+/// Path of the websocket endpoint on the matchmaker's http server
+pub const MATCHMAKER_PATH: &str = "/matchmaker/ws";
+
+/// What the client asks for, as JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchRequest {
+    pub game: String,
+    pub version: String,
+}
+
+/// What the matchmaker replies with, as JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MatchResponse {
+    /// Progress update, shown on the connect screen
+    Status { status: String },
+    /// Matchmaking failed, the client shows the code and message
+    Error { code: u16, error: String },
+    /// A server is ready for the client
+    Ready {
+        /// Base64 encoded lightyear connect token
+        token: String,
+        ip: String,
+        port: u16,
+        /// For webtransport on wasm, where the server's self-signed certificate isn't trusted
+        cert_digest: String,
+    },
+}
+
+impl MatchRequest {
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| format!("malformed request: {e}"))
+    }
+}
+
+impl MatchResponse {
+    pub fn status(status: impl Into<String>) -> Self {
+        MatchResponse::Status {
+            status: status.into(),
+        }
+    }
+
+    pub fn error(code: u16, error: impl Into<String>) -> Self {
+        MatchResponse::Error {
+            code,
+            error: error.into(),
+        }
+    }
+
+    /// Whether this ends the exchange
+    pub fn is_final(&self) -> bool {
+        !matches!(self, MatchResponse::Status { .. })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("response should serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_from_json() {
+        let request =
+            MatchRequest::from_json(r#"{"game":"bevygap-spaceships","version":"0.1.0"}"#).unwrap();
+        assert_eq!(
+            request,
+            MatchRequest {
+                game: "bevygap-spaceships".to_string(),
+                version: "0.1.0".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_request() {
+        assert!(MatchRequest::from_json(r#"{"game":"bevygap-spaceships"}"#).is_err());
+        assert!(MatchRequest::from_json("hello").is_err());
+    }
+
+    /// Each response's JSON, and that it reads back as the same response
+    fn round_trip(response: MatchResponse, json: &str) {
+        assert_eq!(response.to_json(), json);
+        let parsed: MatchResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed, response);
+    }
+
+    #[test]
+    fn status_round_trip() {
+        round_trip(
+            MatchResponse::status("Finding a server..."),
+            r#"{"status":"Finding a server..."}"#,
+        );
+    }
+
+    #[test]
+    fn error_round_trip() {
+        round_trip(
+            MatchResponse::error(503, "No servers"),
+            r#"{"code":503,"error":"No servers"}"#,
+        );
+    }
+
+    #[test]
+    fn ready_round_trip() {
+        round_trip(
+            MatchResponse::Ready {
+                token: "AQID".to_string(),
+                ip: "127.0.0.1".to_string(),
+                port: 6420,
+                cert_digest: "ab:cd".to_string(),
+            },
+            r#"{"token":"AQID","ip":"127.0.0.1","port":6420,"cert_digest":"ab:cd"}"#,
+        );
+    }
+
+    #[test]
+    fn only_status_is_not_final() {
+        assert!(!MatchResponse::status("...").is_final());
+        assert!(MatchResponse::error(500, "oops").is_final());
+    }
+}